@@ -1,6 +1,19 @@
-use actix_web::{get, post, delete, web, App, HttpResponse, HttpServer, Responder};
+use actix_service::ServiceFactory;
+use actix_web::dev::{Body, Payload, Service, ServiceRequest, ServiceResponse};
+use chrono::{DateTime, Utc};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::{get, post, put, delete, web, App, FromRequest, HttpRequest, HttpResponse, HttpServer, Responder};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder};
 use serde::{Deserialize, Serialize};
-use rhyzome_heed::{Rhyzome, Relation, Node};
+use std::future::{ready, Ready};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::Instrument;
+use rhyzome_heed::{ChangeEntry, GraphStats, Rhyzome, Relation, Node};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Post {
@@ -15,6 +28,32 @@ struct CreatePostRequest {
     zone: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct UpdatePostRequest {
+    content: String,
+    zone: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenRequest {
+    permission: String,
+    #[serde(default)]
+    zones: Vec<String>,
+    ttl_secs: Option<u64>,
+    #[serde(default)]
+    jwt: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenResponse {
+    token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TagRequest {
+    tag: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct RelationQuery {
     relation_name: String,
@@ -28,9 +67,186 @@ struct RelationQueryResponse {
     relations: Vec<Relation>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct RelationQuerySummary {
+    relation_name: String,
+    count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NodesQuery {
+    after: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PathQuery {
+    from: String,
+    to: String,
+    relation: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NodesPage {
+    ids: Vec<String>,
+    next: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthStatus {
+    status: &'static str,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StatsResponse {
+    #[serde(flatten)]
+    graph: GraphStats,
+    relations_by_name: BTreeMap<String, usize>,
+}
+
+const DEFAULT_PAGE_LIMIT: usize = 50;
+const MAX_PAGE_LIMIT: usize = 500;
+const DEFAULT_RATE_LIMIT: usize = 120;
+const DEFAULT_RATE_WINDOW: Duration = Duration::from_secs(60);
+const RATE_LIMIT_PRUNE_EVERY: usize = 1024;
+const DEFAULT_JWT_TTL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone)]
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    latency: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let labels = ["route", "method", "status"];
+        let requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route, method and status"),
+            &labels,
+        )?;
+        let latency = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route, method and status"),
+            &labels,
+        )?;
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(latency.clone()))?;
+        Ok(Self { registry, requests, latency })
+    }
+
+    fn observe(&self, route: &str, method: &str, status: u16, started: Instant) {
+        let status = status.to_string();
+        let labels = [route, method, status.as_str()];
+        self.requests.with_label_values(&labels).inc();
+        self.latency.with_label_values(&labels).observe(started.elapsed().as_secs_f64());
+    }
+}
+
+// Sliding-window log: each key may make `limit` requests in any `window`.
+struct RateLimiter {
+    limit: usize,
+    window: Duration,
+    hits: Mutex<HashMap<String, VecDeque<Instant>>>,
+    checks: AtomicUsize,
+}
+
+impl RateLimiter {
+    fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            hits: Mutex::new(HashMap::new()),
+            checks: AtomicUsize::new(0),
+        }
+    }
+
+    // Records the request if allowed, otherwise returns how long until it would be
+    fn check(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut hits = self.hits.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Every so often forget keys whose whole log has aged out, so the map
+        // tracks only active callers without a full scan per request
+        if self.checks.fetch_add(1, AtomicOrdering::Relaxed).is_multiple_of(RATE_LIMIT_PRUNE_EVERY) {
+            hits.retain(|_, log| log.back().is_some_and(|last| now.duration_since(*last) < self.window));
+        }
+
+        let log = hits.entry(key.to_owned()).or_default();
+        while log.front().is_some_and(|first| now.duration_since(*first) >= self.window) {
+            log.pop_front();
+        }
+        if log.len() >= self.limit {
+            let oldest = log.front().copied().unwrap_or(now);
+            return Err(self.window.saturating_sub(now.duration_since(oldest)));
+        }
+        log.push_back(now);
+        Ok(())
+    }
+}
+
+enum ZoneAccess {
+    Granted,
+    Forbidden,
+    Unauthorized,
+}
+
+// One permission, limited to `zones`; an empty list means every zone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Grant {
+    permission: String,
+    #[serde(default)]
+    zones: Vec<String>,
+}
+
+impl Grant {
+    // The legacy string form: `permission` or `permission:zone`
+    fn parse(value: &str) -> Self {
+        match value.split_once(':') {
+            Some((permission, zone)) => Grant { permission: permission.to_owned(), zones: vec![zone.to_owned()] },
+            None => Grant { permission: value.to_owned(), zones: Vec::new() },
+        }
+    }
+}
+
+// Which zones a token may use one permission in
+enum ZoneScope {
+    All,
+    Only(Vec<String>),
+}
+
+impl ZoneScope {
+    fn allows(&self, zone: &str) -> bool {
+        match self {
+            ZoneScope::All => true,
+            ZoneScope::Only(zones) => zones.iter().any(|z| z == zone),
+        }
+    }
+}
+
+// What a token node's data holds. Older tokens store `permissions` as legacy
+// strings instead of grants, or are a bare comma-separated list that never expires.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredToken {
+    #[serde(default)]
+    grants: Vec<Grant>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    permissions: Vec<String>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JwtClaims {
+    permission: String,
+    #[serde(default)]
+    zones: Vec<String>,
+    exp: u64,
+}
+
+#[derive(Clone)]
 struct TokenManager {
     tokens_rhyzome: Rhyzome,
     admin_password: String,
+    jwt_secret: Option<Vec<u8>>,
 }
 
 impl TokenManager {
@@ -38,29 +254,269 @@ impl TokenManager {
         Self {
             tokens_rhyzome,
             admin_password,
+            jwt_secret: None,
         }
     }
 
+    fn with_jwt_secret(mut self, secret: impl Into<Vec<u8>>) -> Self {
+        self.jwt_secret = Some(secret.into());
+        self
+    }
+
+    // Unlike generate_token, a JWT is reusable until it expires and is never stored
+    fn generate_jwt(
+        &self,
+        permission: &str,
+        zones: &[&str],
+        ttl: Duration,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let secret = self.jwt_secret.as_deref().ok_or("JWT secret is not configured")?;
+        let claims = JwtClaims {
+            permission: permission.to_owned(),
+            zones: zones.iter().map(|z| z.to_string()).collect(),
+            exp: (SystemTime::now() + ttl).duration_since(UNIX_EPOCH)?.as_secs(),
+        };
+        let token = jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(secret))?;
+        Ok(token)
+    }
+
+    // None without a secret, or when the token is malformed, forged or expired
+    fn jwt_grants(&self, token: &str) -> Option<Vec<Grant>> {
+        let secret = self.jwt_secret.as_deref()?;
+        let mut validation = Validation::default();
+        validation.leeway = 0;
+        let data = jsonwebtoken::decode::<JwtClaims>(token, &DecodingKey::from_secret(secret), &validation).ok()?;
+        Some(vec![Grant {
+            permission: data.claims.permission,
+            zones: data.claims.zones,
+        }])
+    }
+
     fn generate_token(&self, permission: &str) -> Result<String, Box<dyn std::error::Error>> {
+        self.generate_token_with_permissions(&[permission])
+    }
+
+    fn generate_token_with_permissions(&self, permissions: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+        self.store_token(permissions.iter().map(|p| Grant::parse(p)).collect(), None)
+    }
+
+    fn generate_token_in_zones(
+        &self,
+        permission: &str,
+        zones: &[&str],
+        ttl: Option<Duration>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let grant = Grant {
+            permission: permission.to_owned(),
+            zones: zones.iter().map(|z| z.to_string()).collect(),
+        };
+        let expires_at = ttl.map(chrono::Duration::from_std).transpose()?.map(|ttl| Utc::now() + ttl);
+        self.store_token(vec![grant], expires_at)
+    }
+
+    fn generate_token_with_ttl(&self, permission: &str, ttl: Duration) -> Result<String, Box<dyn std::error::Error>> {
+        let expires_at = Utc::now() + chrono::Duration::from_std(ttl)?;
+        self.store_token(vec![Grant::parse(permission)], Some(expires_at))
+    }
+
+    fn store_token(
+        &self,
+        grants: Vec<Grant>,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
         let token = generate_token_id();
-        self.tokens_rhyzome.store_node(&Node::new(token.clone(), permission.into()))?;
+        let stored = StoredToken {
+            grants,
+            permissions: Vec::new(),
+            expires_at,
+        };
+        self.tokens_rhyzome.add_node(Node::new(token.clone(), serde_json::to_string(&stored)?))?;
         Ok(token)
     }
 
-    fn validate_token(
+    // Multi-use: the token stays valid for later requests
+    fn validate_token_persistent(
         &self,
         token: &str,
         required_permission: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let node = self.tokens_rhyzome.retrieve_node(token)?;
-        match node {
-            Some(node) if node.content == required_permission => {
-                self.tokens_rhyzome.delete_node(token)?;
-                Ok(())
-            }
+        // Zone-limited grants don't pass a check that names no zone
+        match self.zone_scope(token, required_permission)? {
+            Some(ZoneScope::All) => Ok(()),
             _ => Err("Invalid token or insufficient permissions".into()),
         }
     }
+
+    fn check_admin_password(&self, password: &str) -> bool {
+        constant_time_eq(password.as_bytes(), self.admin_password.as_bytes())
+    }
+
+    // Like validate_token_persistent, but a grant limited to some zones
+    // passes only for those zones.
+    fn authorize_in_zone(
+        &self,
+        token: &str,
+        permission: &str,
+        zone: &str,
+    ) -> Result<ZoneAccess, Box<dyn std::error::Error>> {
+        match self.zone_scope(token, permission)? {
+            Some(scope) if scope.allows(zone) => Ok(ZoneAccess::Granted),
+            Some(_) => Ok(ZoneAccess::Forbidden),
+            None => Ok(ZoneAccess::Unauthorized),
+        }
+    }
+
+    // None when the token is unknown or lacks `permission` in every zone
+    fn zone_scope(&self, token: &str, permission: &str) -> Result<Option<ZoneScope>, Box<dyn std::error::Error>> {
+        let grants = match self.resolve_token(token)? {
+            Some(grants) => grants,
+            None => return Ok(None),
+        };
+
+        let mut zones: Vec<String> = Vec::new();
+        let mut granted = false;
+        for grant in grants.into_iter().filter(|g| g.permission == permission) {
+            if grant.zones.is_empty() {
+                return Ok(Some(ZoneScope::All));
+            }
+            granted = true;
+            zones.extend(grant.zones);
+        }
+        Ok(if granted { Some(ZoneScope::Only(zones)) } else { None })
+    }
+
+    // Looks the token up without consuming it; an expired token is deleted
+    // and treated as unknown. With a JWT secret set, signed JWTs resolve too.
+    fn resolve_token(&self, token: &str) -> Result<Option<Vec<Grant>>, Box<dyn std::error::Error>> {
+        // JWTs are checked by signature; stored ids never contain the dots they need
+        if let Some(grants) = self.jwt_grants(token) {
+            return Ok(Some(grants));
+        }
+        let node = match self.tokens_rhyzome.get_node(token)? {
+            Some(node) => node,
+            None => return Ok(None),
+        };
+
+        let stored = serde_json::from_str::<StoredToken>(&node.data).unwrap_or_else(|_| StoredToken {
+            grants: Vec::new(),
+            permissions: node.data
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_owned)
+                .collect(),
+            expires_at: None,
+        });
+
+        if stored.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            self.tokens_rhyzome.delete_node(token)?;
+            return Ok(None);
+        }
+        let mut grants = stored.grants;
+        grants.extend(stored.permissions.iter().map(|p| Grant::parse(p)));
+        Ok(Some(grants))
+    }
+}
+
+// Compares every byte so the time taken doesn't reveal how much of a guess
+// was right; only the length can leak.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+const INSECURE_ADMIN_PASSWORD: &str = "admin_password123";
+
+fn parse_admin_password(value: Option<String>) -> Result<String, Box<dyn std::error::Error>> {
+    match value {
+        None => Err("ADMIN_PASSWORD is not set".into()),
+        Some(password) if password.trim().is_empty() => Err("ADMIN_PASSWORD is empty".into()),
+        Some(password) if password == INSECURE_ADMIN_PASSWORD => {
+            Err("ADMIN_PASSWORD is still the insecure default".into())
+        }
+        Some(password) => Ok(password),
+    }
+}
+
+/// Soft authentication: a valid `read` token elevates the caller, anything
+/// else (no header, malformed header, bad token) is treated as anonymous.
+enum Viewer {
+    Anonymous,
+    Authenticated,
+}
+
+impl FromRequest for Viewer {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let token = parse_bearer_token(req.headers());
+        let token_manager = req.app_data::<web::Data<TokenManager>>();
+
+        let viewer = match (token, token_manager) {
+            (Some(token), Some(token_manager)) if token_manager.validate_token_persistent(&token, "read").is_ok() => {
+                Viewer::Authenticated
+            }
+            _ => Viewer::Anonymous,
+        };
+        ready(Ok(viewer))
+    }
+}
+
+/// The token from an `Authorization: Bearer TOKEN` header. Missing headers,
+/// other schemes and malformed values are rejected with 401.
+struct BearerToken(String);
+
+impl FromRequest for BearerToken {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let token = parse_bearer_token(req.headers())
+            .map(BearerToken)
+            .ok_or_else(|| actix_web::error::ErrorUnauthorized("Unauthorized"));
+        ready(token)
+    }
+}
+
+/// Proof the request carried the admin password in `X-Admin-Password`;
+/// anything else is rejected with 401.
+struct Admin;
+
+impl FromRequest for Admin {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let password = req
+            .headers()
+            .get("X-Admin-Password")
+            .and_then(|header_value| header_value.to_str().ok())
+            .unwrap_or("");
+        let admin = match req.app_data::<web::Data<TokenManager>>() {
+            Some(token_manager) if token_manager.check_admin_password(password) => Ok(Admin),
+            _ => Err(actix_web::error::ErrorUnauthorized("Unauthorized")),
+        };
+        ready(admin)
+    }
+}
+
+fn parse_bearer_token(headers: &HeaderMap) -> Option<String> {
+    let header_str = headers.get("Authorization")?.to_str().ok()?;
+    let mut token_parts = header_str.split_whitespace();
+    match (token_parts.next(), token_parts.next(), token_parts.next()) {
+        (Some(scheme), Some(token), None) if scheme.eq_ignore_ascii_case("Bearer") => Some(token.to_owned()),
+        _ => None,
+    }
+}
+
+fn is_valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && !tag.chars().any(|c| c.is_whitespace() || matches!(c, ',' | '/' | ':'))
 }
 
 fn generate_token_id() -> String {
@@ -68,7 +524,45 @@ fn generate_token_id() -> String {
     // For simplicity, we're using a random 8-character alphanumeric string
     use rand::distributions::Alphanumeric;
     use rand::{thread_rng, Rng};
-    thread_rng().sample_iter(&Alphanumeric).take(8).collect()
+    thread_rng().sample_iter(&Alphanumeric).take(8).map(char::from).collect()
+}
+
+// Mints a token for one permission, optionally limited to zones or given a
+// lifetime. Stored tokens never expire unless ttl_secs is set; a JWT can't be
+// revoked, so it always expires, after an hour by default.
+#[post("/tokens")]
+async fn issue_token(
+    payload: web::Json<TokenRequest>,
+    token_manager: web::Data<TokenManager>,
+    _: Admin,
+) -> impl Responder {
+    if payload.permission.is_empty() {
+        return HttpResponse::BadRequest().body("Missing permission");
+    }
+    if payload.jwt && token_manager.jwt_secret.is_none() {
+        return HttpResponse::BadRequest().body("JWT secret is not configured");
+    }
+
+    let permission = payload.permission.as_str();
+    let zones: Vec<&str> = payload.zones.iter().map(String::as_str).collect();
+    let ttl = payload.ttl_secs.map(Duration::from_secs);
+    let issued = if payload.jwt {
+        token_manager.generate_jwt(permission, &zones, ttl.unwrap_or(DEFAULT_JWT_TTL))
+    } else if !zones.is_empty() {
+        token_manager.generate_token_in_zones(permission, &zones, ttl)
+    } else if let Some(ttl) = ttl {
+        token_manager.generate_token_with_ttl(permission, ttl)
+    } else {
+        token_manager.generate_token(permission)
+    };
+
+    match issued {
+        Ok(token) => HttpResponse::Ok().json(TokenResponse { token }),
+        Err(e) => {
+            tracing::error!("Failed to issue token: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to issue token")
+        }
+    }
 }
 
 #[post("/posts")]
@@ -76,88 +570,177 @@ async fn create_post(
     payload: web::Json<CreatePostRequest>,
     rhyzome: web::Data<Rhyzome>,
     token_manager: web::Data<TokenManager>,
-    req: actix_web::HttpRequest,
+    BearerToken(token): BearerToken,
 ) -> impl Responder {
-    let authorization_header = req.headers().get("Authorization");
-    let token = match authorization_header {
-        Some(header_value) => {
-            let header_str = header_value.to_str().unwrap_or("");
-            // Extract the token from the header (e.g., "Bearer TOKEN_VALUE")
-            let token_parts: Vec<&str> = header_str.split_whitespace().collect();
-            if token_parts.len() == 2 {
-                token_parts[1].to_owned()
-            } else {
-                return HttpResponse::Unauthorized().body("Unauthorized");
-            }
-        }
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
-
     let permission = "create";
 
-    // Validate the token and required permission
-    match token_manager.validate_token(&token, permission) {
-        Ok(()) => {
+    // Validate the token and that it may write to the requested zone
+    match token_manager.authorize_in_zone(&token, permission, &payload.zone) {
+        Ok(ZoneAccess::Granted) => {
             let post_id = generate_token_id();
             let post = Post {
                 id: post_id.clone(),
                 content: payload.content.clone(),
                 zone: payload.zone.clone(),
             };
-            rhyzome
-                .store_node(&Node::new(post_id, serde_json::to_vec(&post).unwrap()))
-                .unwrap();
-            HttpResponse::Ok().body("Post created successfully")
+            let data = match serde_json::to_string(&post) {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::error!("Failed to encode post {}: {:?}", post_id, e);
+                    return HttpResponse::InternalServerError().body("Failed to create post");
+                }
+            };
+            match rhyzome.add_node(Node::new(post_id, data)) {
+                Ok(()) => HttpResponse::Ok().body("Post created successfully"),
+                Err(e) => {
+                    tracing::error!("Failed to create post: {:?}", e);
+                    HttpResponse::InternalServerError().body("Failed to create post")
+                }
+            }
         }
+        Ok(ZoneAccess::Forbidden) => HttpResponse::Forbidden().body("Token is not scoped to this zone"),
+        Ok(ZoneAccess::Unauthorized) => HttpResponse::Unauthorized().body("Unauthorized"),
         Err(e) => {
-            eprintln!("Failed to validate token: {:?}", e);
+            tracing::warn!("Failed to validate token: {:?}", e);
             HttpResponse::Unauthorized().body("Unauthorized")
         }
     }
 }
 
+// Returns a bare array of the posts in zones the token may read; a page
+// shorter than `limit` is the last, otherwise pass the last post's id as
+// `after` for the next one.
+#[get("/posts")]
+async fn list_posts(
+    query: web::Query<NodesQuery>,
+    rhyzome: web::Data<Rhyzome>,
+    token_manager: web::Data<TokenManager>,
+    BearerToken(token): BearerToken,
+) -> impl Responder {
+    let permission = "read";
+
+    let scope = match token_manager.zone_scope(&token, permission) {
+        Ok(Some(scope)) => scope,
+        Ok(None) => return HttpResponse::Unauthorized().body("Unauthorized"),
+        Err(e) => {
+            tracing::warn!("Failed to validate token: {:?}", e);
+            return HttpResponse::Unauthorized().body("Unauthorized");
+        }
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    let mut posts: Vec<Post> = Vec::new();
+    let mut after = query.after.clone();
+    // Posts in other zones are dropped, so keep reading until the page is
+    // full or the store runs out. Nodes that don't decode as a Post are
+    // skipped by page_nodes.
+    loop {
+        let page = match rhyzome.page_nodes::<Post>(after.as_deref(), limit) {
+            Ok((page, _)) => page,
+            Err(e) => {
+                tracing::error!("Failed to list posts: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to list posts");
+            }
+        };
+        let exhausted = page.len() < limit;
+        after = page.last().map(|(id, _)| id.clone());
+        posts.extend(page.into_iter().map(|(_, post)| post).filter(|post| scope.allows(&post.zone)));
+        if exhausted || posts.len() >= limit {
+            break;
+        }
+    }
+    posts.truncate(limit);
+    HttpResponse::Ok().json(posts)
+}
+
+// Loads a post and checks the token may use `permission` in its zone.
+// The Err side is the response to send back as-is.
+fn load_scoped_post(
+    rhyzome: &Rhyzome,
+    token_manager: &TokenManager,
+    token: &str,
+    permission: &str,
+    id: &str,
+) -> Result<(Post, ZoneScope), HttpResponse> {
+    // Resolve the token first so unauthorized callers can't probe which ids exist
+    let scope = match token_manager.zone_scope(token, permission) {
+        Ok(Some(scope)) => scope,
+        Ok(None) => return Err(HttpResponse::Unauthorized().body("Unauthorized")),
+        Err(e) => {
+            tracing::warn!("Failed to validate token: {:?}", e);
+            return Err(HttpResponse::Unauthorized().body("Unauthorized"));
+        }
+    };
+
+    let post: Post = match rhyzome.get_node(id) {
+        Ok(Some(node)) => match serde_json::from_str(&node.data) {
+            Ok(post) => post,
+            Err(e) => {
+                tracing::error!("Failed to decode post {}: {:?}", id, e);
+                return Err(HttpResponse::InternalServerError().body("Failed to retrieve post"));
+            }
+        },
+        Ok(None) => return Err(HttpResponse::NotFound().body("Post not found")),
+        Err(e) => {
+            tracing::error!("Failed to retrieve post: {:?}", e);
+            return Err(HttpResponse::InternalServerError().body("Failed to retrieve post"));
+        }
+    };
+
+    if !scope.allows(&post.zone) {
+        return Err(HttpResponse::Forbidden().body("Token is not scoped to this zone"));
+    }
+    Ok((post, scope))
+}
+
 #[get("/posts/{id}")]
 async fn get_post(
     web::Path(id): web::Path<String>,
     rhyzome: web::Data<Rhyzome>,
     token_manager: web::Data<TokenManager>,
-    req: actix_web::HttpRequest,
+    BearerToken(token): BearerToken,
 ) -> impl Responder {
-    let authorization_header = req.headers().get("Authorization");
-    let token = match authorization_header {
-        Some(header_value) => {
-            let header_str = header_value.to_str().unwrap_or("");
-            // Extract the token from the header (e.g., "Bearer TOKEN_VALUE")
-            let token_parts: Vec<&str> = header_str.split_whitespace().collect();
-            if token_parts.len() == 2 {
-                token_parts[1].to_owned()
-            } else {
-                return HttpResponse::Unauthorized().body("Unauthorized");
-            }
-        }
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
+    let permission = "edit";
 
+    match load_scoped_post(&rhyzome, &token_manager, &token, permission, &id) {
+        Ok((post, _)) => HttpResponse::Ok().json(post),
+        Err(response) => response,
+    }
+}
+
+#[put("/posts/{id}")]
+async fn update_post(
+    web::Path(id): web::Path<String>,
+    payload: web::Json<UpdatePostRequest>,
+    rhyzome: web::Data<Rhyzome>,
+    token_manager: web::Data<TokenManager>,
+    BearerToken(token): BearerToken,
+) -> impl Responder {
     let permission = "edit";
 
-    // Validate the token and required permission
-    match token_manager.validate_token(&token, permission) {
-        Ok(()) => {
-            match rhyzome.retrieve_node(&id) {
-                Ok(Some(node)) => {
-                    let post: Post = serde_json::from_slice(&node.content).unwrap();
-                    HttpResponse::Ok().json(post)
-                }
-                Ok(None) => HttpResponse::NotFound().body("Post not found"),
-                Err(e) => {
-                    eprintln!("Failed to retrieve post: {:?}", e);
-                    HttpResponse::InternalServerError().body("Failed to retrieve post")
-                }
-            }
+    let (mut post, scope) = match load_scoped_post(&rhyzome, &token_manager, &token, permission, &id) {
+        Ok(loaded) => loaded,
+        Err(response) => return response,
+    };
+    // Moving a post needs access to the destination zone as well
+    if !scope.allows(&payload.zone) {
+        return HttpResponse::Forbidden().body("Token is not scoped to this zone");
+    }
+
+    post.content = payload.content.clone();
+    post.zone = payload.zone.clone();
+    let data = match serde_json::to_string(&post) {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::error!("Failed to encode post {}: {:?}", id, e);
+            return HttpResponse::InternalServerError().body("Failed to update post");
         }
+    };
+    match rhyzome.update_node(Node::new(id, data)) {
+        Ok(()) => HttpResponse::Ok().json(post),
         Err(e) => {
-            eprintln!("Failed to validate token: {:?}", e);
-            HttpResponse::Unauthorized().body("Unauthorized")
+            tracing::error!("Failed to update post: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to update post")
         }
     }
 }
@@ -167,89 +750,1226 @@ async fn delete_post(
     web::Path(id): web::Path<String>,
     rhyzome: web::Data<Rhyzome>,
     token_manager: web::Data<TokenManager>,
-    req: actix_web::HttpRequest,
+    BearerToken(token): BearerToken,
 ) -> impl Responder {
-    let authorization_header = req.headers().get("Authorization");
-    let token = match authorization_header {
-        Some(header_value) => {
-            let header_str = header_value.to_str().unwrap_or("");
-            // Extract the token from the header (e.g., "Bearer TOKEN_VALUE")
-            let token_parts: Vec<&str> = header_str.split_whitespace().collect();
-            if token_parts.len() == 2 {
-                token_parts[1].to_owned()
-            } else {
-                return HttpResponse::Unauthorized().body("Unauthorized");
-            }
-        }
-        None => return HttpResponse::Unauthorized().body("Unauthorized"),
-    };
+    let permission = "edit";
+
+    if let Err(response) = load_scoped_post(&rhyzome, &token_manager, &token, permission, &id) {
+        return response;
+    }
+    match rhyzome.delete_node(&id) {
+        Ok(true) => HttpResponse::Ok().body("Post deleted successfully"),
+        Ok(false) => HttpResponse::NotFound().body("Post not found"),
+        Err(e) => {
+            tracing::error!("Failed to delete post: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to delete post")
+        }
+    }
+}
+
+#[post("/posts/{id}/tags")]
+async fn tag_post(
+    web::Path(id): web::Path<String>,
+    payload: web::Json<TagRequest>,
+    rhyzome: web::Data<Rhyzome>,
+    token_manager: web::Data<TokenManager>,
+    BearerToken(token): BearerToken,
+) -> impl Responder {
+    if !is_valid_tag(&payload.tag) {
+        return HttpResponse::BadRequest().body("Invalid tag");
+    }
 
     let permission = "edit";
 
-    // Validate the token and required permission
-    match token_manager.validate_token(&token, permission) {
-        Ok(()) => {
-            match rhyzome.delete_node(&id) {
-                Ok(true) => HttpResponse::Ok().body("Post deleted successfully"),
-                Ok(false) => HttpResponse::NotFound().body("Post not found"),
-                Err(e) => {
-                    eprintln!("Failed to delete post: {:?}", e);
-                    HttpResponse::InternalServerError().body("Failed to delete post")
-                }
-            }
+    if let Err(response) = load_scoped_post(&rhyzome, &token_manager, &token, permission, &id) {
+        return response;
+    }
+    match rhyzome.add_tag(&id, &payload.tag) {
+        Ok(()) => HttpResponse::Ok().body("Post tagged successfully"),
+        Err(e) => {
+            tracing::error!("Failed to tag post: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to tag post")
+        }
+    }
+}
+
+// Only posts in zones the token may read are listed
+#[get("/tags/{tag}/posts")]
+async fn posts_with_tag(
+    web::Path(tag): web::Path<String>,
+    rhyzome: web::Data<Rhyzome>,
+    token_manager: web::Data<TokenManager>,
+    BearerToken(token): BearerToken,
+) -> impl Responder {
+    let permission = "read";
+
+    let scope = match token_manager.zone_scope(&token, permission) {
+        Ok(Some(scope)) => scope,
+        Ok(None) => return HttpResponse::Unauthorized().body("Unauthorized"),
+        Err(e) => {
+            tracing::warn!("Failed to validate token: {:?}", e);
+            return HttpResponse::Unauthorized().body("Unauthorized");
         }
+    };
+
+    if !is_valid_tag(&tag) {
+        return HttpResponse::BadRequest().body("Invalid tag");
+    }
+
+    let ids = match rhyzome.nodes_with_tag(&tag) {
+        Ok(ids) => ids,
         Err(e) => {
-            eprintln!("Failed to validate token: {:?}", e);
-            HttpResponse::Unauthorized().body("Unauthorized")
+            tracing::error!("Failed to list tagged posts: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to list tagged posts");
+        }
+    };
+
+    let mut posts: Vec<Post> = Vec::new();
+    for id in ids {
+        match rhyzome.get_node(&id) {
+            Ok(Some(node)) => {
+                match serde_json::from_str::<Post>(&node.data) {
+                    Ok(post) if scope.allows(&post.zone) => posts.push(post),
+                    _ => {}
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!("Failed to retrieve post: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to retrieve post");
+            }
         }
     }
+    HttpResponse::Ok().json(posts)
 }
 
 #[post("/relations")]
 async fn query_relations(
     payload: web::Json<RelationQuery>,
     rhyzome: web::Data<Rhyzome>,
+    viewer: Viewer,
 ) -> impl Responder {
     let relation_name = payload.relation_name.clone();
     let from_node_id = payload.from_node_id.clone();
     let to_node_id = payload.to_node_id.clone();
 
-    match rhyzome.query_relations(&relation_name, &from_node_id, &to_node_id) {
-        Ok(relations) => {
-            let response = RelationQueryResponse {
-                relation_name,
-                relations,
-            };
-            HttpResponse::Ok().json(response)
+    match rhyzome.get_relation(&relation_name, &from_node_id, &to_node_id) {
+        // Anonymous callers only learn how many relations matched
+        Ok(relation) => {
+            let relations: Vec<Relation> = relation.into_iter().collect();
+            match viewer {
+                Viewer::Authenticated => HttpResponse::Ok().json(RelationQueryResponse {
+                    relation_name,
+                    relations,
+                }),
+                Viewer::Anonymous => HttpResponse::Ok().json(RelationQuerySummary {
+                    relation_name,
+                    count: relations.len(),
+                }),
+            }
         }
         Err(e) => {
-            eprintln!("Failed to query relations: {:?}", e);
+            tracing::error!("Failed to query relations: {:?}", e);
             HttpResponse::InternalServerError().body("Failed to query relations")
         }
     }
 }
 
-#[actix_web::main]
+#[get("/relations/names")]
+async fn relation_names(
+    rhyzome: web::Data<Rhyzome>,
+    token_manager: web::Data<TokenManager>,
+    BearerToken(token): BearerToken,
+) -> impl Responder {
+    let permission = "read";
+
+    // Validate the token and required permission
+    match token_manager.validate_token_persistent(&token, permission) {
+        Ok(()) => match rhyzome.relation_names() {
+            Ok(names) => HttpResponse::Ok().json(names),
+            Err(e) => {
+                tracing::error!("Failed to list relation names: {:?}", e);
+                HttpResponse::InternalServerError().body("Failed to list relation names")
+            }
+        },
+        Err(e) => {
+            tracing::warn!("Failed to validate token: {:?}", e);
+            HttpResponse::Unauthorized().body("Unauthorized")
+        }
+    }
+}
+
+#[get("/nodes")]
+async fn list_nodes(
+    query: web::Query<NodesQuery>,
+    rhyzome: web::Data<Rhyzome>,
+    token_manager: web::Data<TokenManager>,
+    BearerToken(token): BearerToken,
+) -> impl Responder {
+    let permission = "read";
+
+    // Validate the token and required permission
+    match token_manager.validate_token_persistent(&token, permission) {
+        Ok(()) => {
+            let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+            match rhyzome.iter_nodes_paged(query.after.as_deref(), limit) {
+                Ok(ids) => {
+                    // A full page means there may be more after the last id
+                    let next = if ids.len() == limit { ids.last().cloned() } else { None };
+                    HttpResponse::Ok().json(NodesPage { ids, next })
+                }
+                Err(e) => {
+                    tracing::error!("Failed to list nodes: {:?}", e);
+                    HttpResponse::InternalServerError().body("Failed to list nodes")
+                }
+            }
+        }
+        Err(e) => {
+            tracing::warn!("Failed to validate token: {:?}", e);
+            HttpResponse::Unauthorized().body("Unauthorized")
+        }
+    }
+}
+
+#[get("/path")]
+async fn shortest_path(
+    query: web::Query<PathQuery>,
+    rhyzome: web::Data<Rhyzome>,
+    token_manager: web::Data<TokenManager>,
+    BearerToken(token): BearerToken,
+) -> impl Responder {
+    let permission = "read";
+
+    // Validate the token and required permission
+    if let Err(e) = token_manager.validate_token_persistent(&token, permission) {
+        tracing::warn!("Failed to validate token: {:?}", e);
+        return HttpResponse::Unauthorized().body("Unauthorized");
+    }
+
+    for node_id in [&query.from, &query.to] {
+        match rhyzome.get_node(node_id) {
+            Ok(Some(_)) => {}
+            Ok(None) => return HttpResponse::BadRequest().body(format!("Post {} does not exist", node_id)),
+            Err(e) => {
+                tracing::error!("Failed to retrieve node: {:?}", e);
+                return HttpResponse::InternalServerError().body("Failed to find path");
+            }
+        }
+    }
+
+    match rhyzome.shortest_path_detailed(&query.from, &query.to, &query.relation) {
+        Ok(Some(path)) => HttpResponse::Ok().json(path),
+        Ok(None) => HttpResponse::NotFound().body("No path found"),
+        Err(e) => {
+            tracing::error!("Failed to find path: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to find path")
+        }
+    }
+}
+
+// Stats scan the whole graph, so they're admin-only
+#[get("/stats")]
+async fn graph_stats(rhyzome: web::Data<Rhyzome>, _: Admin) -> impl Responder {
+    let stats = rhyzome.stats().and_then(|graph| {
+        Ok(StatsResponse {
+            graph,
+            relations_by_name: rhyzome.relation_counts_by_name()?,
+        })
+    });
+    match stats {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => {
+            tracing::error!("Failed to compute stats: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to compute stats")
+        }
+    }
+}
+
+#[get("/zones/stats")]
+async fn zone_stats(rhyzome: web::Data<Rhyzome>, _: Admin) -> impl Responder {
+    match rhyzome.zone_stats() {
+        Ok(zones) => HttpResponse::Ok().json(zones),
+        Err(e) => {
+            tracing::error!("Failed to compute zone stats: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to compute zone stats")
+        }
+    }
+}
+
+fn sse_event(entry: &ChangeEntry) -> web::Bytes {
+    let data = serde_json::to_string(entry).unwrap_or_default();
+    web::Bytes::from(format!("id: {}\ndata: {}\n\n", entry.seq, data))
+}
+
+// The changelog covers every zone, so this needs a read token that isn't zone-limited.
+// Only a Last-Event-ID replays history; without one the stream starts from now.
+#[get("/events")]
+async fn change_events(
+    rhyzome: web::Data<Rhyzome>,
+    token_manager: web::Data<TokenManager>,
+    BearerToken(token): BearerToken,
+    req: actix_web::HttpRequest,
+) -> impl Responder {
+    let permission = "read";
+
+    if let Err(e) = token_manager.validate_token_persistent(&token, permission) {
+        tracing::warn!("Failed to validate token: {:?}", e);
+        return HttpResponse::Unauthorized().body("Unauthorized");
+    }
+
+    let last_event_id: Option<u64> = req
+        .headers()
+        .get("Last-Event-ID")
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|id| id.trim().parse().ok());
+
+    // Subscribe before replaying so nothing committed in between is missed
+    let mut receiver = rhyzome.subscribe();
+    // Where to catch up from if the stream lags before sending anything
+    let subscribed_at = rhyzome.last_change_seq();
+    let backlog = match last_event_id.map(|seq| rhyzome.changes_since(seq)).transpose() {
+        Ok(backlog) => backlog.unwrap_or_default(),
+        Err(e) => {
+            tracing::error!("Failed to read changelog: {:?}", e);
+            return HttpResponse::InternalServerError().body("Failed to read changelog");
+        }
+    };
+
+    let stream = async_stream::stream! {
+        let mut last_seq = last_event_id;
+        for entry in backlog {
+            last_seq = Some(entry.seq);
+            yield Ok::<_, actix_web::Error>(sse_event(&entry));
+        }
+
+        loop {
+            match receiver.recv().await {
+                Ok(entry) if last_seq.is_none_or(|seq| entry.seq > seq) => {
+                    last_seq = Some(entry.seq);
+                    yield Ok(sse_event(&entry));
+                }
+                Ok(_) => {}
+                // Fell behind the channel; catch up from the stored changelog
+                Err(RecvError::Lagged(_)) => {
+                    let since = last_seq.unwrap_or(subscribed_at);
+                    match rhyzome.changes_since(since) {
+                        Ok(missed) => {
+                            for entry in missed {
+                                last_seq = Some(entry.seq);
+                                yield Ok(sse_event(&entry));
+                            }
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to read changelog: {:?}", e);
+                            break;
+                        }
+                    }
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .streaming(Box::pin(stream))
+}
+
+// Every route and middleware, shared by main and the tests
+fn build_app(
+    rhyzome: Rhyzome,
+    token_manager: TokenManager,
+    metrics: Metrics,
+    rate_limiter: Arc<RateLimiter>,
+) -> App<
+    impl ServiceFactory<
+        Config = (),
+        Request = ServiceRequest,
+        Response = ServiceResponse<Body>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+    Body,
+> {
+    let route_metrics = metrics.clone();
+    let rate_tokens = token_manager.clone();
+    App::new()
+        .wrap_fn(move |req, srv| {
+            // Keyed by token when it resolves, otherwise by client address;
+            // made-up tokens would otherwise each get a fresh budget
+            let token = parse_bearer_token(req.headers())
+                .filter(|token| matches!(rate_tokens.resolve_token(token), Ok(Some(_))));
+            let key = match token {
+                Some(token) => format!("token:{}", token),
+                None => format!("ip:{}", req.connection_info().realip_remote_addr().unwrap_or("unknown")),
+            };
+            let outcome = match rate_limiter.check(&key) {
+                Ok(()) => Ok(srv.call(req)),
+                Err(retry_after) => {
+                    // Round up so clients never retry a moment too early
+                    let retry_secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                    Err(req.into_response(
+                        HttpResponse::TooManyRequests()
+                            .header("Retry-After", retry_secs.max(1).to_string())
+                            .body("Too many requests"),
+                    ))
+                }
+            };
+            async move {
+                match outcome {
+                    Ok(fut) => fut.await,
+                    Err(res) => Ok(res),
+                }
+            }
+        })
+        .wrap_fn(move |req, srv| {
+            // Label by route pattern (e.g. /posts/{id}) so ids don't explode cardinality
+            let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_owned());
+            let method = req.method().to_string();
+            let started = Instant::now();
+            let metrics = route_metrics.clone();
+            let fut = srv.call(req);
+            async move {
+                let res = fut.await;
+                let status = match &res {
+                    Ok(res) => res.status().as_u16(),
+                    Err(_) => 500,
+                };
+                metrics.observe(&route, &method, status, started);
+                res
+            }
+        })
+        .wrap_fn(|req, srv| {
+            // Reuse the caller's X-Request-Id if it sent one, otherwise mint one
+            let request_id = req
+                .headers()
+                .get("X-Request-Id")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+                .unwrap_or_else(generate_token_id);
+            let span = tracing::info_span!("request", request_id = %request_id);
+            let fut = srv.call(req);
+            async move {
+                let mut res = fut.instrument(span).await?;
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    res.headers_mut().insert(HeaderName::from_static("x-request-id"), value);
+                }
+                Ok(res)
+            }
+        })
+        .data(rhyzome)
+        .data(token_manager)
+        .data(metrics)
+        .service(issue_token)
+        .service(create_post)
+        .service(list_posts)
+        .service(get_post)
+        .service(update_post)
+        .service(delete_post)
+        .service(tag_post)
+        .service(posts_with_tag)
+        .service(query_relations)
+        .service(relation_names)
+        .service(list_nodes)
+        .service(shortest_path)
+        .service(graph_stats)
+        .service(zone_stats)
+        .service(change_events)
+        .service(export_metrics)
+        .service(healthz)
+        .service(readyz)
+}
+
+#[get("/healthz")]
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().json(HealthStatus { status: "ok" })
+}
+
+// Ready once both heed environments can serve a read transaction
+#[get("/readyz")]
+async fn readyz(rhyzome: web::Data<Rhyzome>, token_manager: web::Data<TokenManager>) -> impl Responder {
+    match rhyzome.count_nodes().and_then(|_| token_manager.tokens_rhyzome.count_nodes()) {
+        Ok(_) => HttpResponse::Ok().json(HealthStatus { status: "ready" }),
+        Err(e) => {
+            tracing::error!("Readiness check failed: {:?}", e);
+            HttpResponse::ServiceUnavailable().json(HealthStatus { status: "unavailable" })
+        }
+    }
+}
+
+#[get("/metrics")]
+async fn export_metrics(metrics: web::Data<Metrics>) -> impl Responder {
+    let mut buffer = Vec::new();
+    match TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer) {
+        Ok(()) => HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(buffer),
+        Err(e) => {
+            tracing::error!("Failed to encode metrics: {:?}", e);
+            HttpResponse::InternalServerError().body("Failed to encode metrics")
+        }
+    }
+}
+
+#[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Create a new Rhyzome instance using Heed for storing posts
-    let rhyzome = Rhyzome::new("./rhyzome.heed").unwrap();
+    tracing_subscriber::fmt::init();
+
+    // Posts live in their own environment and are the only nodes every
+    // /posts, /tags, /nodes, /path and /stats handler sees
+    let rhyzome = Rhyzome::open("./rhyzome.heed").unwrap();
 
-    // Create a separate Rhyzome instance for storing tokens
-    let tokens_rhyzome = Rhyzome::new("./tokens-rhyzome.heed").unwrap();
+    // Tokens get a separate environment touched only by TokenManager, so a
+    // token id can never collide with or be listed as a post
+    let tokens_rhyzome = Rhyzome::open("./tokens-rhyzome.heed").unwrap();
+
+    // Refuse to start without a real admin password
+    let admin_password = match parse_admin_password(std::env::var("ADMIN_PASSWORD").ok()) {
+        Ok(password) => password,
+        Err(e) => {
+            tracing::error!("Refusing to start: {}", e);
+            return Err(std::io::Error::other(e.to_string()));
+        }
+    };
 
     // Initialize token manager
-    let token_manager = TokenManager::new(tokens_rhyzome, "admin_password123".to_owned());
+    let mut token_manager = TokenManager::new(tokens_rhyzome, admin_password);
+    if let Ok(secret) = std::env::var("JWT_SECRET") {
+        token_manager = token_manager.with_jwt_secret(secret);
+    }
+
+    let metrics = Metrics::new().map_err(std::io::Error::other)?;
+
+    let rate_limit = std::env::var("RATE_LIMIT_REQUESTS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_RATE_LIMIT);
+    let rate_window = std::env::var("RATE_LIMIT_WINDOW_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .map_or(DEFAULT_RATE_WINDOW, Duration::from_secs);
+    let rate_limiter = Arc::new(RateLimiter::new(rate_limit, rate_window));
 
     HttpServer::new(move || {
-        App::new()
-            .data(rhyzome.clone())
-            .data(token_manager.clone())
-            .service(create_post)
-            .service(get_post)
-            .service(delete_post)
-            .service(query_relations)
+        build_app(rhyzome.clone(), token_manager.clone(), metrics.clone(), rate_limiter.clone())
     })
     .bind("127.0.0.1:8080")?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use futures_util::StreamExt;
+
+    const ADMIN_PASSWORD: &str = "correct-horse-battery-staple";
+    const JWT_SECRET: &str = "test-jwt-secret";
+
+    // Posts and tokens each get their own directory, as in main
+    fn temp_state() -> ([tempfile::TempDir; 2], Rhyzome, TokenManager) {
+        let posts_dir = tempfile::tempdir().unwrap();
+        let tokens_dir = tempfile::tempdir().unwrap();
+        let rhyzome = Rhyzome::open(posts_dir.path()).unwrap();
+        let tokens_rhyzome = Rhyzome::open(tokens_dir.path()).unwrap();
+        let token_manager = TokenManager::new(tokens_rhyzome, ADMIN_PASSWORD.to_owned()).with_jwt_secret(JWT_SECRET);
+        ([posts_dir, tokens_dir], rhyzome, token_manager)
+    }
+
+    // The full app, middleware included, with a rate limit tests won't hit unless they pass one
+    macro_rules! init_app {
+        ($rhyzome:expr, $token_manager:expr) => {
+            init_app!($rhyzome, $token_manager, RateLimiter::new(10_000, DEFAULT_RATE_WINDOW))
+        };
+        ($rhyzome:expr, $token_manager:expr, $rate_limiter:expr) => {
+            test::init_service(build_app(
+                $rhyzome.clone(),
+                $token_manager.clone(),
+                Metrics::new().unwrap(),
+                Arc::new($rate_limiter),
+            ))
+            .await
+        };
+    }
+
+
+    fn bearer(token: &str) -> String {
+        format!("Bearer {}", token)
+    }
+
+    fn relation() -> Relation {
+        Relation {
+            data: String::new(),
+            timestamp: Utc::now(),
+            weight: 1.0,
+            expires_at: None,
+        }
+    }
+
+    fn expired_jwt(permission: &str) -> String {
+        let claims = JwtClaims {
+            permission: permission.to_owned(),
+            zones: Vec::new(),
+            exp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() - 60,
+        };
+        jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(JWT_SECRET.as_bytes())).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn relation_names_are_listed_once_each() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        rhyzome.add_relation("follows", "a", "b", relation()).unwrap();
+        rhyzome.add_relation("follows", "b", "c", relation()).unwrap();
+        rhyzome.add_relation("likes", "a", "c", relation()).unwrap();
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/relations/names").header("Authorization", bearer(&token)).to_request();
+        let names: Vec<String> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(names, ["follows", "likes"]);
+    }
+    #[actix_rt::test]
+    async fn request_ids_are_echoed_or_generated() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/healthz").header("X-Request-Id", "req-42").to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.headers().get("X-Request-Id").unwrap(), "req-42");
+
+        let req = TestRequest::get().uri("/healthz").to_request();
+        let res = test::call_service(&mut app, req).await;
+        let generated = res.headers().get("X-Request-Id").unwrap().to_str().unwrap();
+        assert_eq!(generated.len(), 8);
+
+        // Errors from extractors get one too
+        let req = TestRequest::get().uri("/nodes").to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().contains_key("X-Request-Id"));
+    }
+
+    #[actix_rt::test]
+    async fn node_pages_visit_every_node_once() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        for i in 0..7 {
+            rhyzome.add_node(Node::new(format!("node-{}", i), "")).unwrap();
+        }
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let mut seen: Vec<String> = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let uri = match &after {
+                Some(after) => format!("/nodes?limit=3&after={}", after),
+                None => "/nodes?limit=3".to_owned(),
+            };
+            let req = TestRequest::get().uri(&uri).header("Authorization", bearer(&token)).to_request();
+            let page: NodesPage = test::read_body_json(test::call_service(&mut app, req).await).await;
+            assert!(page.ids.len() <= 3);
+            seen.extend(page.ids);
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break,
+            }
+        }
+
+        let expected: Vec<String> = (0..7).map(|i| format!("node-{}", i)).collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn constant_time_eq_compares_whole_values() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+    }
+
+    #[test]
+    fn admin_password_must_be_set_and_not_the_default() {
+        assert!(parse_admin_password(None).is_err());
+        assert!(parse_admin_password(Some("  ".to_owned())).is_err());
+        assert!(parse_admin_password(Some(INSECURE_ADMIN_PASSWORD.to_owned())).is_err());
+        assert_eq!(parse_admin_password(Some("s3cret".to_owned())).unwrap(), "s3cret");
+    }
+
+    #[actix_rt::test]
+    async fn anonymous_relation_queries_only_get_a_count() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        rhyzome.add_relation("follows", "a", "b", relation()).unwrap();
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+        let query = RelationQuery {
+            relation_name: "follows".to_owned(),
+            from_node_id: "a".to_owned(),
+            to_node_id: "b".to_owned(),
+        };
+
+        let req = TestRequest::post().uri("/relations").set_json(&query).to_request();
+        let summary: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(summary, serde_json::json!({ "relation_name": "follows", "count": 1 }));
+
+        // A bad token is treated as anonymous rather than rejected
+        let req = TestRequest::post()
+            .uri("/relations")
+            .header("Authorization", bearer("bogus"))
+            .set_json(&query)
+            .to_request();
+        let summary: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(summary["count"], 1);
+        assert!(summary.get("relations").is_none());
+
+        let req = TestRequest::post()
+            .uri("/relations")
+            .header("Authorization", bearer(&token))
+            .set_json(&query)
+            .to_request();
+        let detailed: RelationQueryResponse = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(detailed.relation_name, "follows");
+        assert_eq!(detailed.relations.len(), 1);
+    }
+
+    #[test]
+    fn bearer_token_needs_the_bearer_scheme() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_bearer_token(&headers), None);
+
+        headers.insert(actix_web::http::header::AUTHORIZATION, HeaderValue::from_static("Basic dXNlcjpwYXNz"));
+        assert_eq!(parse_bearer_token(&headers), None);
+
+        headers.insert(actix_web::http::header::AUTHORIZATION, HeaderValue::from_static("Bearer"));
+        assert_eq!(parse_bearer_token(&headers), None);
+
+        headers.insert(actix_web::http::header::AUTHORIZATION, HeaderValue::from_static("Bearer abc extra"));
+        assert_eq!(parse_bearer_token(&headers), None);
+
+        headers.insert(actix_web::http::header::AUTHORIZATION, HeaderValue::from_static("bearer abc123"));
+        assert_eq!(parse_bearer_token(&headers).as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn rate_limiter_allows_limit_requests_per_window() {
+        let limiter = RateLimiter::new(2, Duration::from_millis(100));
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_ok());
+        assert!(limiter.check("a").is_err());
+        assert!(limiter.check("b").is_ok());
+
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(limiter.check("a").is_ok());
+    }
+
+    #[test]
+    fn token_grants_every_listed_permission() {
+        let (_dirs, _, token_manager) = temp_state();
+        let token = token_manager.generate_token_with_permissions(&["read", "create:news"]).unwrap();
+
+        assert_eq!(token_manager.resolve_token(&token).unwrap().unwrap().len(), 2);
+        assert!(token_manager.validate_token_persistent(&token, "read").is_ok());
+        assert!(matches!(token_manager.authorize_in_zone(&token, "create", "news"), Ok(ZoneAccess::Granted)));
+        assert!(matches!(token_manager.authorize_in_zone(&token, "create", "sports"), Ok(ZoneAccess::Forbidden)));
+        assert!(matches!(token_manager.authorize_in_zone(&token, "edit", "news"), Ok(ZoneAccess::Unauthorized)));
+        // A zone-limited grant doesn't pass a check that names no zone
+        assert!(token_manager.validate_token_persistent(&token, "create").is_err());
+    }
+
+    #[test]
+    fn tokens_with_a_ttl_expire_and_are_deleted() {
+        let (_dirs, _, token_manager) = temp_state();
+        let token = token_manager.generate_token_with_ttl("read", Duration::from_secs(1)).unwrap();
+        assert!(token_manager.validate_token_persistent(&token, "read").is_ok());
+
+        std::thread::sleep(Duration::from_millis(1100));
+        assert!(token_manager.validate_token_persistent(&token, "read").is_err());
+        assert!(token_manager.tokens_rhyzome.get_node(&token).unwrap().is_none());
+    }
+
+    #[test]
+    fn jwts_are_checked_by_signature_permission_and_expiry() {
+        let (_dirs, _, token_manager) = temp_state();
+        let jwt = token_manager.generate_jwt("read", &[], Duration::from_secs(60)).unwrap();
+        assert!(token_manager.validate_token_persistent(&jwt, "read").is_ok());
+        assert!(token_manager.validate_token_persistent(&jwt, "edit").is_err());
+        assert!(token_manager.validate_token_persistent(&expired_jwt("read"), "read").is_err());
+
+        // Never stored, so it still resolves after being used
+        assert_eq!(token_manager.tokens_rhyzome.count_nodes().unwrap(), 0);
+        assert!(token_manager.validate_token_persistent(&jwt, "read").is_ok());
+
+        let (_other_dirs, _, other_manager) = temp_state();
+        let forged = other_manager
+            .with_jwt_secret("another-secret")
+            .generate_jwt("read", &[], Duration::from_secs(60))
+            .unwrap();
+        assert!(token_manager.resolve_token(&forged).unwrap().is_none());
+    }
+
+    #[test]
+    fn zone_limited_jwts_keep_their_zones() {
+        let (_dirs, _, token_manager) = temp_state();
+        let jwt = token_manager.generate_jwt("create", &["news"], Duration::from_secs(60)).unwrap();
+
+        assert!(matches!(token_manager.authorize_in_zone(&jwt, "create", "news"), Ok(ZoneAccess::Granted)));
+        assert!(matches!(token_manager.authorize_in_zone(&jwt, "create", "sports"), Ok(ZoneAccess::Forbidden)));
+    }
+
+    #[actix_rt::test]
+    async fn issuing_tokens_needs_the_admin_password() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let mut app = init_app!(rhyzome, token_manager);
+        let body = TokenRequest { permission: "read".to_owned(), zones: Vec::new(), ttl_secs: None, jwt: false };
+
+        let req = TestRequest::post().uri("/tokens").set_json(&body).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::post()
+            .uri("/tokens")
+            .header("X-Admin-Password", "wrong")
+            .set_json(&body)
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::post()
+            .uri("/tokens")
+            .header("X-Admin-Password", ADMIN_PASSWORD)
+            .set_json(&body)
+            .to_request();
+        let issued: TokenResponse = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert!(token_manager.validate_token_persistent(&issued.token, "read").is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn issued_jwts_and_zoned_tokens_authorize_requests() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let mut app = init_app!(rhyzome, token_manager);
+        let body = TokenRequest {
+            permission: "create".to_owned(),
+            zones: vec!["news".to_owned()],
+            ttl_secs: Some(60),
+            jwt: true,
+        };
+
+        let req = TestRequest::post()
+            .uri("/tokens")
+            .header("X-Admin-Password", ADMIN_PASSWORD)
+            .set_json(&body)
+            .to_request();
+        let issued: TokenResponse = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(issued.token.matches('.').count(), 2);
+        assert!(matches!(token_manager.authorize_in_zone(&issued.token, "create", "news"), Ok(ZoneAccess::Granted)));
+        assert!(matches!(token_manager.authorize_in_zone(&issued.token, "create", "sports"), Ok(ZoneAccess::Forbidden)));
+    }
+
+    #[test]
+    fn legacy_comma_separated_tokens_still_resolve() {
+        let (_dirs, _, token_manager) = temp_state();
+        token_manager.tokens_rhyzome.add_node(Node::new("legacy", "read, edit:news")).unwrap();
+
+        assert!(token_manager.validate_token_persistent("legacy", "read").is_ok());
+        assert!(matches!(token_manager.authorize_in_zone("legacy", "edit", "news"), Ok(ZoneAccess::Granted)));
+        assert!(token_manager.resolve_token("unknown").unwrap().is_none());
+    }
+
+    #[test]
+    fn tokens_stay_valid_across_requests() {
+        let (_dirs, _, token_manager) = temp_state();
+        let token = token_manager.generate_token("read").unwrap();
+
+        assert!(token_manager.validate_token_persistent(&token, "read").is_ok());
+        assert!(token_manager.validate_token_persistent(&token, "read").is_ok());
+        assert!(token_manager.validate_token_persistent(&token, "edit").is_err());
+    }
+
+    #[actix_rt::test]
+    async fn posts_can_only_be_created_in_the_token_zones() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let news = token_manager.generate_token_in_zones("create", &["news"], None).unwrap();
+        let anywhere = token_manager.generate_token("create").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+        let post_to = |zone: &str, token: &str| {
+            TestRequest::post()
+                .uri("/posts")
+                .header("Authorization", bearer(token))
+                .set_json(&CreatePostRequest { content: "hello".to_owned(), zone: zone.to_owned() })
+                .to_request()
+        };
+
+        let res = test::call_service(&mut app, post_to("news", &news)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = test::call_service(&mut app, post_to("sports", &news)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res = test::call_service(&mut app, post_to("sports", &anywhere)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = test::call_service(&mut app, post_to("news", "unknown")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let zones = rhyzome.zone_stats().unwrap();
+        assert_eq!(zones["news"].post_count, 1);
+        assert_eq!(zones["sports"].post_count, 1);
+    }
+
+    #[actix_rt::test]
+    async fn tagged_posts_are_listed_within_the_token_zones() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        seed_post(&rhyzome, "p1", "news");
+        seed_post(&rhyzome, "p2", "sports");
+        let editor = token_manager.generate_token("edit").unwrap();
+        let reader = token_manager.generate_token("read").unwrap();
+        let news_reader = token_manager.generate_token_in_zones("read", &["news"], None).unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        for id in ["p1", "p2"] {
+            let req = TestRequest::post()
+                .uri(&format!("/posts/{}/tags", id))
+                .header("Authorization", bearer(&editor))
+                .set_json(&TagRequest { tag: "rust".to_owned() })
+                .to_request();
+            assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+        }
+
+        let req = TestRequest::post()
+            .uri("/posts/p1/tags")
+            .header("Authorization", bearer(&editor))
+            .set_json(&TagRequest { tag: "has space".to_owned() })
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::BAD_REQUEST);
+
+        let req = TestRequest::get().uri("/tags/rust/posts").header("Authorization", bearer(&reader)).to_request();
+        let posts: Vec<Post> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let mut ids: Vec<String> = posts.into_iter().map(|post| post.id).collect();
+        ids.sort();
+        assert_eq!(ids, ["p1", "p2"]);
+
+        let req = TestRequest::get().uri("/tags/rust/posts").header("Authorization", bearer(&news_reader)).to_request();
+        let posts: Vec<Post> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].id, "p1");
+    }
+
+    fn seed_post(rhyzome: &Rhyzome, id: &str, zone: &str) {
+        let post = Post {
+            id: id.to_owned(),
+            content: format!("post {}", id),
+            zone: zone.to_owned(),
+        };
+        rhyzome.add_node(Node::new(id, serde_json::to_string(&post).unwrap())).unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn metrics_count_requests_by_route_pattern() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        seed_post(&rhyzome, "p1", "news");
+        let token = token_manager.generate_token("edit").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        for uri in ["/healthz", "/posts/p1", "/no-such-route"] {
+            let req = TestRequest::get().uri(uri).header("Authorization", bearer(&token)).to_request();
+            test::call_service(&mut app, req).await;
+        }
+
+        let req = TestRequest::get().uri("/metrics").to_request();
+        let body = test::read_body(test::call_service(&mut app, req).await).await;
+        let text = std::str::from_utf8(&body).unwrap();
+        assert!(text.contains("http_requests_total"));
+        assert!(text.contains("http_request_duration_seconds_count"));
+        assert!(text.contains(r#"route="/healthz""#));
+        assert!(text.contains(r#"route="/posts/{id}""#));
+        assert!(text.contains(r#"route="unmatched""#));
+        assert!(!text.contains("p1"));
+    }
+
+    #[actix_rt::test]
+    async fn stats_need_the_admin_password() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        for id in ["a", "b", "c"] {
+            rhyzome.add_node(Node::new(id, "")).unwrap();
+        }
+        rhyzome.add_relation("follows", "a", "b", relation()).unwrap();
+        rhyzome.add_relation("follows", "a", "c", relation()).unwrap();
+        rhyzome.add_relation("likes", "b", "c", relation()).unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/stats").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::get().uri("/stats").header("X-Admin-Password", ADMIN_PASSWORD).to_request();
+        let stats: StatsResponse = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(stats.graph.node_count, 3);
+        assert_eq!(stats.graph.relation_count, 3);
+        assert_eq!(stats.graph.max_out_degree, 2);
+        assert_eq!(stats.graph.max_in_degree, 2);
+        assert_eq!(stats.relations_by_name["follows"], 2);
+        assert_eq!(stats.relations_by_name["likes"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn events_stream_committed_changes() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        rhyzome.add_node(Node::new("before", "")).unwrap();
+        let token = token_manager.generate_token("read").unwrap();
+        let news_reader = token_manager.generate_token_in_zones("read", &["news"], None).unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/events").header("Authorization", bearer(&news_reader)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::get().uri("/events").header("Authorization", bearer(&token)).to_request();
+        let mut res = test::call_service(&mut app, req).await;
+        assert_eq!(res.headers().get("Content-Type").unwrap(), "text/event-stream");
+        let mut body = res.take_body();
+
+        rhyzome.add_node(Node::new("after", "")).unwrap();
+        let chunk = actix_rt::time::timeout(Duration::from_secs(5), body.next()).await.unwrap().unwrap().unwrap();
+        let frame = std::str::from_utf8(&chunk).unwrap();
+        assert!(frame.starts_with("id: 2\ndata: "));
+        assert!(frame.ends_with("\n\n"));
+        assert!(frame.contains(r#""kind":"node_put""#));
+        assert!(frame.contains(r#""id":"after""#));
+    }
+
+    #[actix_rt::test]
+    async fn events_replay_from_last_event_id() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        for id in ["a", "b", "c"] {
+            rhyzome.add_node(Node::new(id, "")).unwrap();
+        }
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get()
+            .uri("/events")
+            .header("Authorization", bearer(&token))
+            .header("Last-Event-ID", "1")
+            .to_request();
+        let mut body = test::call_service(&mut app, req).await.take_body();
+
+        for seq in [2, 3] {
+            let chunk = actix_rt::time::timeout(Duration::from_secs(5), body.next()).await.unwrap().unwrap().unwrap();
+            assert!(std::str::from_utf8(&chunk).unwrap().starts_with(&format!("id: {}\n", seq)));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn events_catch_up_when_the_stream_lags_before_its_first_event() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/events").header("Authorization", bearer(&token)).to_request();
+        let mut body = test::call_service(&mut app, req).await.take_body();
+
+        // More changes than the broadcast channel holds, before anything is read
+        rhyzome.add_nodes((0..2000).map(|i| Node::new(format!("n{:04}", i), ""))).unwrap();
+        for seq in 1..=20 {
+            let chunk = actix_rt::time::timeout(Duration::from_secs(5), body.next()).await.unwrap().unwrap().unwrap();
+            assert!(std::str::from_utf8(&chunk).unwrap().starts_with(&format!("id: {}\n", seq)));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn path_returns_nodes_and_relations() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        for id in ["a", "b", "c", "island"] {
+            rhyzome.add_node(Node::new(id, "")).unwrap();
+        }
+        rhyzome.add_relation("link", "a", "b", relation()).unwrap();
+        rhyzome.add_relation("link", "b", "c", relation()).unwrap();
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+        let path_req = |query: &str| {
+            TestRequest::get()
+                .uri(&format!("/path?{}", query))
+                .header("Authorization", bearer(&token))
+                .to_request()
+        };
+
+        let path: serde_json::Value =
+            test::read_body_json(test::call_service(&mut app, path_req("from=a&to=c&relation=link")).await).await;
+        let ids: Vec<&str> = path["nodes"].as_array().unwrap().iter().map(|node| node["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["a", "b", "c"]);
+        assert_eq!(path["relations"].as_array().unwrap().len(), 2);
+
+        let res = test::call_service(&mut app, path_req("from=a&to=missing&relation=link")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let res = test::call_service(&mut app, path_req("from=a&to=island&relation=link")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn zone_stats_group_posts_by_zone() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        seed_post(&rhyzome, "p1", "news");
+        seed_post(&rhyzome, "p2", "news");
+        seed_post(&rhyzome, "p3", "sports");
+        rhyzome.add_node(Node::new("not-a-post", "plain text")).unwrap();
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/zones/stats").header("Authorization", bearer(&token)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::get().uri("/zones/stats").header("X-Admin-Password", ADMIN_PASSWORD).to_request();
+        let zones: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let zones = zones.as_object().unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones["news"]["post_count"], 2);
+        assert_eq!(zones["sports"]["post_count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn protected_routes_need_a_bearer_token() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/posts").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::get().uri("/posts").header("Authorization", format!("Basic {}", token)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::get().uri("/posts").header("Authorization", bearer(&token)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn updating_a_post_replaces_its_content() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        seed_post(&rhyzome, "p1", "news");
+        let token = token_manager.generate_token("edit").unwrap();
+        let news_editor = token_manager.generate_token_in_zones("edit", &["news"], None).unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+        let update = |id: &str, zone: &str, token: &str| {
+            TestRequest::put()
+                .uri(&format!("/posts/{}", id))
+                .header("Authorization", bearer(token))
+                .set_json(&UpdatePostRequest { content: "edited".to_owned(), zone: zone.to_owned() })
+                .to_request()
+        };
+
+        let updated: Post = test::read_body_json(test::call_service(&mut app, update("p1", "news", &token)).await).await;
+        assert_eq!(updated.content, "edited");
+        let stored: Post = serde_json::from_str(&rhyzome.get_node("p1").unwrap().unwrap().data).unwrap();
+        assert_eq!(stored.content, "edited");
+
+        let res = test::call_service(&mut app, update("missing", "news", &token)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert!(rhyzome.get_node("missing").unwrap().is_none());
+
+        // Moving a post out of the token's zones is refused
+        let res = test::call_service(&mut app, update("p1", "sports", &news_editor)).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[actix_rt::test]
+    async fn posts_are_listed_in_pages() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        for i in 0..15 {
+            seed_post(&rhyzome, &format!("post-{:02}", i), "news");
+        }
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/posts?limit=10").header("Authorization", bearer(&token)).to_request();
+        let first: Vec<Post> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(first.len(), 10);
+
+        let uri = format!("/posts?limit=10&after={}", first.last().unwrap().id);
+        let req = TestRequest::get().uri(&uri).header("Authorization", bearer(&token)).to_request();
+        let second: Vec<Post> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(second.len(), 5);
+
+        let ids: Vec<String> = first.into_iter().chain(second).map(|post| post.id).collect();
+        let expected: Vec<String> = (0..15).map(|i| format!("post-{:02}", i)).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[actix_rt::test]
+    async fn zone_limited_readers_only_list_their_zones() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        for i in 0..12 {
+            let zone = if i % 3 == 0 { "news" } else { "sports" };
+            seed_post(&rhyzome, &format!("post-{:02}", i), zone);
+        }
+        let token = token_manager.generate_token_in_zones("read", &["news"], None).unwrap();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        // Fills the page from past several pages' worth of other zones' posts
+        let req = TestRequest::get().uri("/posts?limit=3").header("Authorization", bearer(&token)).to_request();
+        let first: Vec<Post> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        let ids: Vec<&str> = first.iter().map(|post| post.id.as_str()).collect();
+        assert_eq!(ids, ["post-00", "post-03", "post-06"]);
+
+        let uri = format!("/posts?limit=3&after={}", first.last().unwrap().id);
+        let req = TestRequest::get().uri(&uri).header("Authorization", bearer(&token)).to_request();
+        let second: Vec<Post> = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].id, "post-09");
+    }
+
+    #[actix_rt::test]
+    async fn token_ids_never_collide_with_post_ids() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let token = token_manager.generate_token_with_permissions(&["read", "edit"]).unwrap();
+        // A post that happens to share the token's id
+        seed_post(&rhyzome, &token, "news");
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/nodes").header("Authorization", bearer(&token)).to_request();
+        let page: NodesPage = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(page.ids, std::slice::from_ref(&token));
+
+        let req = TestRequest::get()
+            .uri(&format!("/posts/{}", token))
+            .header("Authorization", bearer(&token))
+            .to_request();
+        let post: Post = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(post.zone, "news");
+
+        let req = TestRequest::delete()
+            .uri(&format!("/posts/{}", token))
+            .header("Authorization", bearer(&token))
+            .to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+        assert!(token_manager.validate_token_persistent(&token, "read").is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn requests_over_the_rate_limit_get_429() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let token = token_manager.generate_token("read").unwrap();
+        let mut app = init_app!(rhyzome, token_manager, RateLimiter::new(3, DEFAULT_RATE_WINDOW));
+
+        for _ in 0..3 {
+            let req = TestRequest::get().uri("/healthz").to_request();
+            assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+        }
+        let req = TestRequest::get().uri("/healthz").to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = res.headers().get("Retry-After").unwrap().to_str().unwrap().parse().unwrap();
+        assert!((1..=60).contains(&retry_after));
+
+        // A valid token has its own budget; a made-up one shares the address's
+        let req = TestRequest::get().uri("/healthz").header("Authorization", bearer(&token)).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::OK);
+        let req = TestRequest::get().uri("/healthz").header("Authorization", bearer("made-up")).to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[actix_rt::test]
+    async fn health_and_readiness_report_ok() {
+        let (_dirs, rhyzome, token_manager) = temp_state();
+        let mut app = init_app!(rhyzome, token_manager);
+
+        let req = TestRequest::get().uri("/healthz").to_request();
+        let health: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(health, serde_json::json!({ "status": "ok" }));
+
+        let req = TestRequest::get().uri("/readyz").to_request();
+        let ready: serde_json::Value = test::read_body_json(test::call_service(&mut app, req).await).await;
+        assert_eq!(ready, serde_json::json!({ "status": "ready" }));
+    }
+}
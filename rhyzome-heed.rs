@@ -1,92 +1,615 @@
-use anyhow::{Result, Context};
+use anyhow::{anyhow, bail, Result, Context};
 use chrono::{DateTime, Utc};
-use heed::{EnvOpenOptions, Database, RwTxn, RoTxn, ByteSlice};
+use heed::{EnvOpenOptions, Database, RwTxn, RoTxn, BytesDecode};
 use heed::types::*;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::{Bound, Deref, DerefMut};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, TryLockError};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const MAX_ALL_PAIRS_NODES: usize = 10_000;
+const SIMRANK_DECAY: f64 = 0.8;
+const CHANGE_CHANNEL_CAPACITY: usize = 1024;
+const DEFAULT_MAP_SIZE: usize = 10 * 1024 * 1024;
+const MAX_DBS: u32 = 8;
+
+type RelationValidator = Arc<dyn Fn(&Relation) -> Result<()> + Send + Sync>;
+type WriteTxn<'a> = Gated<'a, RwTxn<'a>>;
+// (relation name, from id, to id, relation)
+pub type RelationEntry = (String, String, String, Relation);
+// (from id, to id, decoded relation data)
+pub type TypedRelation<T> = (String, String, T);
 
 pub struct Rhyzome {
-    node_db: Database<Str, OwnedType<Node>>,
-    relations_db: Database<Str, OwnedType<Relation>>,
+    node_db: Database<Str, SerdeJson<Node>>,
+    relations_db: Database<Str, SerdeJson<Relation>>,
+    degrees_db: Database<Str, SerdeJson<(usize, usize)>>,
+    tags_db: Database<Str, SerdeJson<BTreeSet<String>>>,
+    neighbors_db: Database<Str, SerdeJson<Vec<String>>>,
     env: heed::Env,
+    // Every transaction holds this shared; resizing the map takes it exclusively
+    txn_gate: Arc<RwLock<()>>,
+    // LMDB allows one writer; queue writers here rather than inside LMDB
+    write_lock: Arc<Mutex<()>>,
+    relation_validators: HashMap<String, RelationValidator>,
+    changelog: Arc<Changelog>,
 }
 
 impl Rhyzome {
     pub fn new() -> Result<Self> {
-        fs::create_dir_all(Path::new("data").join("rhyzome.mdb")).context("Failed to create data directory")?;
-        let env = EnvOpenOptions::new()
-            .open(Path::new("data").join("rhyzome.mdb")).context("Failed to open heed environment")?;
+        Rhyzome::open(Path::new("data").join("rhyzome.mdb"))
+    }
+
+    // Each path is its own environment; two Rhyzomes opened on different
+    // paths never share keys.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        fs::create_dir_all(path).context("Failed to create data directory")?;
+        // Safety: nothing else in this process opens the same path with other flags
+        let env = unsafe {
+            EnvOpenOptions::new()
+                .map_size(DEFAULT_MAP_SIZE)
+                .max_dbs(MAX_DBS)
+                .open(path)
+        }
+        .context("Failed to open heed environment")?;
+        let mut wtxn = env.write_txn().context("Failed to begin write transaction")?;
 
-        let node_db: Database<Str, OwnedType<Node>> = env.create_database(Some("node"))
+        let node_db: Database<Str, SerdeJson<Node>> = env.create_database(&mut wtxn, Some("node"))
             .context("Failed to create or open node database")?;
 
-        let relations_db: Database<Str, OwnedType<Relation>> = env.create_database(Some("relations"))
+        let relations_db: Database<Str, SerdeJson<Relation>> = env.create_database(&mut wtxn, Some("relations"))
             .context("Failed to create or open relations database")?;
 
+        let degrees_db: Database<Str, SerdeJson<(usize, usize)>> = env.create_database(&mut wtxn, Some("degrees"))
+            .context("Failed to create or open degrees database")?;
+
+        let tags_db: Database<Str, SerdeJson<BTreeSet<String>>> = env.create_database(&mut wtxn, Some("tags"))
+            .context("Failed to create or open tags database")?;
+
+        let changelog_db: Database<Str, SerdeJson<ChangeEntry>> = env.create_database(&mut wtxn, Some("changelog"))
+            .context("Failed to create or open changelog database")?;
+
+        let neighbors_db: Database<Str, SerdeJson<Vec<String>>> = env.create_database(&mut wtxn, Some("neighbors"))
+            .context("Failed to create or open neighbors database")?;
+        wtxn.commit().context("Failed to commit transaction")?;
+
+        let next_seq = {
+            let ro_txn = env.read_txn().context("Failed to begin read transaction")?;
+            let last = changelog_db.last(&ro_txn).context("Failed to read changelog")?;
+            last.map_or(1, |(_, entry)| entry.seq + 1)
+        };
+        let (sender, _) = broadcast::channel(CHANGE_CHANNEL_CAPACITY);
+        let changelog = Changelog {
+            db: changelog_db,
+            sender,
+            next_seq: AtomicU64::new(next_seq),
+            batching: None,
+            buffer: Mutex::new(Vec::new()),
+        };
+
         Ok(Rhyzome {
             node_db,
             relations_db,
+            degrees_db,
+            tags_db,
+            neighbors_db,
             env,
+            txn_gate: Arc::new(RwLock::new(())),
+            write_lock: Arc::new(Mutex::new(())),
+            relation_validators: HashMap::new(),
+            changelog: Arc::new(changelog),
+        })
+    }
+
+    // Buffer changelog entries in memory and write them in batches instead of
+    // alongside every mutation. Call before sharing the Rhyzome.
+    pub fn with_changelog_batching(mut self, batching: ChangelogBatching) -> Result<Self> {
+        Arc::get_mut(&mut self.changelog)
+            .context("Changelog batching must be configured before the Rhyzome is shared")?
+            .batching = Some(batching);
+        Ok(self)
+    }
+
+    pub fn spawn_changelog_flush(&self) -> Option<thread::JoinHandle<()>> {
+        let interval = self.changelog.batching.as_ref()?.flush_interval;
+        let env = self.env.clone();
+        let txn_gate = self.txn_gate.clone();
+        let write_lock = self.write_lock.clone();
+        // Weak so the thread neither keeps the changelog alive nor counts as a handle
+        let changelog = Arc::downgrade(&self.changelog);
+        Some(thread::spawn(move || loop {
+            thread::sleep(interval);
+            let changelog = match changelog.upgrade() {
+                Some(changelog) => changelog,
+                None => return,
+            };
+            if let Err(e) = flush_changelog_buffer(&env, &txn_gate, &write_lock, &changelog) {
+                eprintln!("Failed to flush changelog: {:?}", e);
+            }
+        }))
+    }
+
+    pub fn flush_changelog(&self) -> Result<usize> {
+        flush_changelog_buffer(&self.env, &self.txn_gate, &self.write_lock, &self.changelog)
+    }
+
+    // Live feed of committed changes; pair with changes_since to catch up first
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEntry> {
+        self.changelog.sender.subscribe()
+    }
+
+    // The newest seq recorded so far, or 0 before any change; a subscriber
+    // that falls behind can catch up with changes_since from here
+    pub fn last_change_seq(&self) -> u64 {
+        self.changelog.next_seq.load(AtomicOrdering::SeqCst).saturating_sub(1)
+    }
+
+    pub fn changes_since(&self, seq: u64) -> Result<Vec<ChangeEntry>> {
+        // Copy the buffer before opening the snapshot: an entry flushed in
+        // between shows up in both and is deduplicated by seq, never in neither.
+        let buffered: Vec<ChangeEntry> = self.changelog.buffer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .filter(|entry| entry.seq > seq)
+            .cloned()
+            .collect();
+
+        let ro_txn = self.read_txn()?;
+        let after = changelog_key(seq);
+        let cursor = self.changelog.db.range(&ro_txn, &(Bound::Excluded(after.as_str()), Bound::Unbounded))?;
+        let mut entries: BTreeMap<u64, ChangeEntry> = BTreeMap::new();
+
+        for res in cursor {
+            let (_, entry) = res?;
+            entries.insert(entry.seq, entry);
+        }
+        for entry in buffered {
+            entries.insert(entry.seq, entry);
+        }
+
+        Ok(entries.into_values().collect())
+    }
+
+    pub fn snapshot(&self) -> Result<Snapshot<'_>> {
+        Ok(Snapshot {
+            rhyzome: self,
+            txn: self.read_txn()?,
         })
     }
 
+    pub fn spawn_auto_grow(&self, config: AutoGrow) -> thread::JoinHandle<()> {
+        let env = self.env.clone();
+        let txn_gate = self.txn_gate.clone();
+        thread::spawn(move || loop {
+            thread::sleep(config.check_interval);
+            if let Err(e) = grow_map_if_needed(&env, &txn_gate, &config) {
+                eprintln!("Failed to grow map size: {:?}", e);
+            }
+        })
+    }
+
+    fn read_txn(&self) -> Result<Gated<'_, RoTxn<'_>>> {
+        let gate = self.txn_gate.read().map_err(|_| anyhow!("Transaction gate poisoned"))?;
+        let txn = self.env.read_txn().context("Failed to begin read transaction")?;
+        Ok(Gated { txn, _gate: gate, _writer: None, changelog: None, pending: Vec::new() })
+    }
+
+    fn write_txn(&self) -> Result<WriteTxn<'_>> {
+        // Take the writer slot before the gate so queued writers don't hold off a resize
+        let writer = self.write_lock.lock().map_err(|_| anyhow!("Write lock poisoned"))?;
+        let gate = self.txn_gate.read().map_err(|_| anyhow!("Transaction gate poisoned"))?;
+        let txn = self.env.write_txn().context("Failed to begin write transaction")?;
+        Ok(Gated {
+            txn,
+            _gate: gate,
+            _writer: Some(writer),
+            changelog: Some(&self.changelog),
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn register_relation_validator(
+        &mut self,
+        relation_name: &str,
+        validator: impl Fn(&Relation) -> Result<()> + Send + Sync + 'static,
+    ) {
+        self.relation_validators.insert(relation_name.to_string(), Arc::new(validator));
+    }
+
     pub fn add_node(&self, node: Node) -> Result<()> {
-        let mut txn = self.env.write_txn().context("Failed to begin write transaction")?;
-        self.node_db.put(&mut txn, &node.id, &node).context("Failed to add node")?;
+        let mut txn = self.write_txn()?;
+        self.put_node(&mut txn, &node)?;
         txn.commit().context("Failed to commit transaction")?;
         Ok(())
     }
 
+    // Dropping the transaction on error aborts it, so a failed batch writes nothing.
+    pub fn add_nodes(&self, nodes: impl IntoIterator<Item = Node>) -> Result<usize> {
+        let mut txn = self.write_txn()?;
+        let mut written = 0;
+        for node in nodes {
+            self.put_node(&mut txn, &node)?;
+            written += 1;
+        }
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(written)
+    }
+
+    // An undecodable value fails with RhyzomeError::Corrupt; get_node_raw
+    // still returns its bytes.
     pub fn get_node(&self, node_id: &str) -> Result<Option<Node>> {
-        let ro_txn = self.env.read_txn().context("Failed to begin read transaction")?;
-        let result = self.node_db.get(&ro_txn, &node_id).context("Failed to retrieve node")?;
-        Ok(result)
+        let ro_txn = self.read_txn()?;
+        self.decode_node(&ro_txn, node_id)
+    }
+
+    fn decode_node(&self, txn: &RoTxn, node_id: &str) -> Result<Option<Node>> {
+        let raw = self.node_db
+            .remap_data_type::<Bytes>()
+            .get(txn, node_id)
+            .context("Failed to retrieve node")?;
+        match raw {
+            Some(bytes) => match SerdeJson::<Node>::bytes_decode(bytes) {
+                Ok(node) => Ok(Some(node)),
+                Err(e) => Err(RhyzomeError::Corrupt {
+                    id: node_id.to_string(),
+                    detail: format!("{} byte value does not decode as a node: {}", bytes.len(), e),
+                }
+                .into()),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_node_raw(&self, node_id: &str) -> Result<Option<Vec<u8>>> {
+        let ro_txn = self.read_txn()?;
+        let raw = self.node_db
+            .remap_data_type::<Bytes>()
+            .get(&ro_txn, node_id)
+            .context("Failed to retrieve node")?;
+        Ok(raw.map(<[u8]>::to_vec))
     }
 
     pub fn update_node(&self, node: Node) -> Result<()> {
-        let mut txn = self.env.write_txn().context("Failed to begin write transaction")?;
-        self.node_db.put(&mut txn, &node.id, &node).context("Failed to update node")?;
+        let mut txn = self.write_txn()?;
+        self.put_node(&mut txn, &node)?;
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    pub fn swap_node_data(&self, a: &str, b: &str) -> Result<()> {
+        let mut txn = self.write_txn()?;
+        let mut node_a = self.node_db.get(&txn, a)
+            .context("Failed to retrieve node")?
+            .with_context(|| format!("Node {} does not exist", a))?;
+        let mut node_b = self.node_db.get(&txn, b)
+            .context("Failed to retrieve node")?
+            .with_context(|| format!("Node {} does not exist", b))?;
+
+        std::mem::swap(&mut node_a.data, &mut node_b.data);
+        let now = Utc::now();
+        node_a.timestamp = now;
+        node_b.timestamp = now;
+
+        self.put_node(&mut txn, &node_a)?;
+        self.put_node(&mut txn, &node_b)?;
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    // Returns whether the node existed.
+    pub fn delete_node(&self, node_id: &str) -> Result<bool> {
+        let mut txn = self.write_txn()?;
+        let existed = self.remove_node(&mut txn, node_id)?;
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(existed)
+    }
+
+    pub fn delete_nodes(&self, node_ids: &[&str]) -> Result<usize> {
+        let (deleted, _) = self.delete_nodes_with(node_ids, false)?;
+        Ok(deleted)
+    }
+
+    // Same as delete_nodes but also drops every relation touching the deleted ids
+    pub fn delete_nodes_cascade(&self, node_ids: &[&str]) -> Result<usize> {
+        let (deleted, _) = self.delete_nodes_with(node_ids, true)?;
+        Ok(deleted)
+    }
+
+    // Returns how many relations went with the node, not whether it existed
+    pub fn delete_node_cascade(&self, node_id: &str) -> Result<usize> {
+        let (_, relations_removed) = self.delete_nodes_with(&[node_id], true)?;
+        Ok(relations_removed)
+    }
+
+    fn delete_nodes_with(&self, node_ids: &[&str], cascade: bool) -> Result<(usize, usize)> {
+        let mut txn = self.write_txn()?;
+        let mut deleted = 0;
+        for node_id in node_ids {
+            if self.remove_node(&mut txn, node_id)? {
+                deleted += 1;
+            }
+        }
+
+        let mut relations_removed = 0;
+        if cascade {
+            let doomed: Vec<(String, String, String)> = self.relation_entries(&txn)?
+                .into_iter()
+                .filter(|(_, id1, id2, _)| node_ids.contains(&id1.as_str()) || node_ids.contains(&id2.as_str()))
+                .map(|(name, id1, id2, _)| (name, id1, id2))
+                .collect();
+            for (name, id1, id2) in &doomed {
+                if self.remove_relation(&mut txn, name, id1, id2)? {
+                    relations_removed += 1;
+                }
+            }
+        }
+
+        txn.commit().context("Failed to commit transaction")?;
+        Ok((deleted, relations_removed))
+    }
+
+    pub fn add_tag(&self, node_id: &str, tag: &str) -> Result<()> {
+        let mut txn = self.write_txn()?;
+        let mut node_ids = self.tags_db.get(&txn, tag).context("Failed to retrieve tag")?.unwrap_or_default();
+        node_ids.insert(node_id.to_string());
+        self.tags_db.put(&mut txn, tag, &node_ids).context("Failed to add tag")?;
         txn.commit().context("Failed to commit transaction")?;
         Ok(())
     }
 
-    pub fn delete_node(&self, node_id: &str) -> Result<()> {
-        let mut txn = self.env.write_txn().context("Failed to begin write transaction")?;
-        self.node_db.delete(&mut txn, &node_id).context("Failed to delete node")?;
+    pub fn remove_tag(&self, node_id: &str, tag: &str) -> Result<()> {
+        let mut txn = self.write_txn()?;
+        if let Some(mut node_ids) = self.tags_db.get(&txn, tag).context("Failed to retrieve tag")? {
+            node_ids.remove(node_id);
+            self.put_tag(&mut txn, tag, &node_ids)?;
+        }
         txn.commit().context("Failed to commit transaction")?;
         Ok(())
     }
+
+    pub fn nodes_with_tag(&self, tag: &str) -> Result<Vec<String>> {
+        let ro_txn = self.read_txn()?;
+        let node_ids = self.tags_db.get(&ro_txn, tag).context("Failed to retrieve tag")?.unwrap_or_default();
+        Ok(node_ids.into_iter().collect())
+    }
     
     pub fn iter_nodes(&self) -> Result<Vec<String>> {
-        let ro_txn = self.env.read_txn().context("Failed to begin read transaction")?;
-        let cursor = self.nodes_db.iter(&ro_txn)?;
+        let ro_txn = self.read_txn()?;
+        let cursor = self.node_db.iter(&ro_txn)?;
         let mut result: Vec<String> = Vec::new();
 
         for res in cursor {
             let (node_key, _) = res?;
-            let node_key_str = String::from_utf8(node_key.to_vec())
-                .context("Failed to convert node key to String")?;
-            result.push(node_key_str);
+            result.push(node_key.to_string());
         }
 
         Ok(result)
     }
     
+    pub fn count_nodes(&self) -> Result<usize> {
+        let ro_txn = self.read_txn()?;
+        let count = self.node_db.len(&ro_txn).context("Failed to count nodes")?;
+        Ok(count as usize)
+    }
+
+    // Counts stored entries, including expired relations not yet purged
+    pub fn count_relations(&self) -> Result<usize> {
+        let ro_txn = self.read_txn()?;
+        let count = self.relations_db.len(&ro_txn).context("Failed to count relations")?;
+        Ok(count as usize)
+    }
+
+    pub fn nodes_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let ro_txn = self.read_txn()?;
+        let mut result: Vec<String> = Vec::new();
+        for res in self.node_db.prefix_iter(&ro_txn, prefix)? {
+            let (node_key, _) = res?;
+            result.push(node_key.to_string());
+        }
+        Ok(result)
+    }
+
+    pub fn iter_nodes_paged(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let ro_txn = self.read_txn()?;
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        let cursor = self.node_db.range(&ro_txn, &(start, Bound::Unbounded))?;
+        let mut result: Vec<String> = Vec::new();
+
+        for res in cursor.take(limit) {
+            let (node_key, _) = res?;
+            result.push(node_key.to_string());
+        }
+
+        Ok(result)
+    }
+
+    // Untyped counterpart of page_nodes, under its own name because
+    // page_nodes is the typed pager. A page shorter than `limit` is the last.
+    pub fn page_node_entries(&self, after: Option<&str>, limit: usize) -> Result<Vec<(String, Node)>> {
+        let ro_txn = self.read_txn()?;
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        let mut result: Vec<(String, Node)> = Vec::new();
+
+        for res in self.node_db.range(&ro_txn, &(start, Bound::Unbounded))?.take(limit) {
+            let (node_key, node) = res?;
+            result.push((node_key.to_string(), node));
+        }
+
+        Ok(result)
+    }
+
+    // Nodes whose data doesn't deserialize into T are skipped and don't count
+    // toward `limit`. The total counts every node, decodable or not.
+    pub fn page_nodes<T: DeserializeOwned>(&self, after: Option<&str>, limit: usize) -> Result<(Vec<(String, T)>, usize)> {
+        let ro_txn = self.read_txn()?;
+        let total = self.node_db.len(&ro_txn).context("Failed to count nodes")? as usize;
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        let mut page: Vec<(String, T)> = Vec::new();
+
+        for res in self.node_db.range(&ro_txn, &(start, Bound::Unbounded))? {
+            if page.len() >= limit {
+                break;
+            }
+            let (node_key, node) = res?;
+            if let Ok(value) = serde_json::from_str::<T>(&node.data) {
+                page.push((node_key.to_string(), value));
+            }
+        }
+
+        Ok((page, total))
+    }
+
+    // Visits nodes in key order until `f` returns false.
+    pub fn for_each_node<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&str, &Node) -> Result<bool>,
+    {
+        let ro_txn = self.read_txn()?;
+        for res in self.node_db.iter(&ro_txn)? {
+            let (node_key, node) = res?;
+            if !f(node_key, &node)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn scan_range<F>(&self, start: Option<&str>, end: Option<&str>, mut f: F) -> Result<()>
+    where
+        F: FnMut(&str, &Node) -> Result<()>,
+    {
+        let ro_txn = self.read_txn()?;
+        let start = start.map_or(Bound::Unbounded, Bound::Included);
+        let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+
+        for res in self.node_db.range(&ro_txn, &(start, end))? {
+            let (node_key, node) = res?;
+            f(node_key, &node)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn recent_nodes(&self, limit: usize) -> Result<Vec<(String, Node)>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let ro_txn = self.read_txn()?;
+        // Min-heap of the newest `limit` seen so far; the oldest is evicted first
+        let mut newest: BinaryHeap<Reverse<(DateTime<Utc>, String)>> = BinaryHeap::new();
+        let mut candidates: HashMap<String, Node> = HashMap::new();
+
+        for res in self.node_db.iter(&ro_txn)? {
+            let (node_key, node) = res?;
+            newest.push(Reverse((node.timestamp, node_key.to_string())));
+            candidates.insert(node_key.to_string(), node);
+            if newest.len() > limit {
+                if let Some(Reverse((_, evicted))) = newest.pop() {
+                    candidates.remove(&evicted);
+                }
+            }
+        }
+
+        let mut result: Vec<(String, Node)> = newest
+            .into_sorted_vec()
+            .into_iter()
+            .filter_map(|Reverse((_, node_key))| {
+                let node = candidates.remove(&node_key)?;
+                Some((node_key, node))
+            })
+            .collect();
+        result.sort_by_key(|(_, node)| Reverse(node.timestamp));
+        Ok(result)
+    }
+
+    pub fn export_changes_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<(Vec<Node>, Vec<RelationEntry>)> {
+        let ro_txn = self.read_txn()?;
+
+        let mut nodes = Vec::new();
+        for res in self.node_db.iter(&ro_txn)? {
+            let (_, node) = res?;
+            if node.timestamp > since {
+                nodes.push(node);
+            }
+        }
+
+        // Expired relations are exported too so the replica sees the same store
+        let relations = self
+            .relation_entries(&ro_txn)?
+            .into_iter()
+            .filter(|(_, _, _, relation)| relation.timestamp > since)
+            .collect();
+
+        Ok((nodes, relations))
+    }
+
+    pub fn apply_delta(
+        &self,
+        nodes: &[Node],
+        relations: &[RelationEntry],
+    ) -> Result<()> {
+        let mut wtxn = self.write_txn()?;
+
+        for node in nodes {
+            let existing = self.node_db.get(&wtxn, &node.id).context("Failed to retrieve node")?;
+            // Last writer wins; ties keep the local copy so reapplying a delta is a no-op
+            if existing.is_none_or(|local| node.timestamp > local.timestamp) {
+                self.put_node(&mut wtxn, node)?;
+            }
+        }
+
+        for (relation_name, node_id1, node_id2, relation) in relations {
+            let relation_key = encode_relation_key(relation_name, node_id1, node_id2);
+            let existing = self.relations_db.get(&wtxn, &relation_key).context("Failed to retrieve relation")?;
+            if existing.is_none_or(|local| relation.timestamp > local.timestamp) {
+                self.put_relation(&mut wtxn, relation_name, node_id1, node_id2, relation)?;
+            }
+        }
+
+        wtxn.commit().context("Failed to commit delta")?;
+        Ok(())
+    }
+
     pub fn query_nodes<F>(&self, filter: F) -> Result<Vec<String>>
     where
         F: Fn(&[u8]) -> bool,
     {
-        let ro_txn = self.env.read_txn().context("Failed to begin read transaction")?;
-        let cursor = self.nodes_db.iter(&ro_txn)?;
+        let ro_txn = self.read_txn()?;
+        let cursor = self.node_db.iter(&ro_txn)?;
         let mut result: Vec<String> = Vec::new();
 
         for res in cursor {
+            // The filter sees the key bytes, never the serialized node
             let (node_key, _) = res?;
-            let node_key_bytes: &[u8] = &node_key;
-            if filter(node_key_bytes) {
-                let node_key_str = String::from_utf8(node_key_bytes.to_vec())
-                    .context("Failed to convert node key to String")?;
-                result.push(node_key_str);
+            if filter(node_key.as_bytes()) {
+                result.push(node_key.to_string());
             }
         }
 
@@ -101,9 +624,34 @@ impl Rhyzome {
         node_id2: &str,
         relation: Relation,
     ) -> Result<()> {
-        let relation_key = format!("{}_{}_{}", relation_name, node_id1, node_id2);
-        let mut txn = self.env.write_txn().context("Failed to begin write transaction")?;
-        self.relations_db.put(&mut txn, &relation_key, &relation).context("Failed to add relation")?;
+        let mut txn = self.write_txn()?;
+        self.put_relation(&mut txn, relation_name, node_id1, node_id2, &relation)?;
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    // Existing endpoint nodes are left untouched; their data is not overwritten.
+    pub fn relate_upsert(
+        &self,
+        relation_name: &str,
+        from: &str,
+        from_data: &str,
+        to: &str,
+        to_data: &str,
+        relation: Relation,
+    ) -> Result<()> {
+        let mut txn = self.write_txn()?;
+        for (node_id, data) in [(from, from_data), (to, to_data)] {
+            if self.node_db.get(&txn, node_id).context("Failed to retrieve node")?.is_none() {
+                let node = Node {
+                    id: node_id.to_string(),
+                    data: data.to_string(),
+                    timestamp: Utc::now(),
+                };
+                self.put_node(&mut txn, &node)?;
+            }
+        }
+        self.put_relation(&mut txn, relation_name, from, to, &relation)?;
         txn.commit().context("Failed to commit transaction")?;
         Ok(())
     }
@@ -114,10 +662,10 @@ impl Rhyzome {
         node_id1: &str,
         node_id2: &str,
     ) -> Result<Option<Relation>> {
-        let relation_key = format!("{}_{}_{}", relation_name, node_id1, node_id2);
-        let ro_txn = self.env.read_txn().context("Failed to begin read transaction")?;
+        let relation_key = encode_relation_key(relation_name, node_id1, node_id2);
+        let ro_txn = self.read_txn()?;
         let result = self.relations_db.get(&ro_txn, &relation_key).context("Failed to retrieve relation")?;
-        Ok(result)
+        Ok(result.filter(|relation| !relation.is_expired()))
     }
 
     pub fn update_relation(
@@ -127,9 +675,8 @@ impl Rhyzome {
         node_id2: &str,
         relation: Relation,
     ) -> Result<()> {
-        let relation_key = format!("{}_{}_{}", relation_name, node_id1, node_id2);
-        let mut txn = self.env.write_txn().context("Failed to begin write transaction")?;
-        self.relations_db.put(&mut txn, &relation_key, &relation).context("Failed to update relation")?;
+        let mut txn = self.write_txn()?;
+        self.put_relation(&mut txn, relation_name, node_id1, node_id2, &relation)?;
         txn.commit().context("Failed to commit transaction")?;
         Ok(())
     }
@@ -140,13 +687,110 @@ impl Rhyzome {
         node_id1: &str,
         node_id2: &str,
     ) -> Result<()> {
-        let relation_key = format!("{}_{}_{}", relation_name, node_id1, node_id2);
-        let mut txn = self.env.write_txn().context("Failed to begin write transaction")?;
-        self.relations_db.delete(&mut txn, &relation_key).context("Failed to delete relation")?;
+        let mut txn = self.write_txn()?;
+        self.remove_relation(&mut txn, relation_name, node_id1, node_id2)?;
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    // Writes a→b and b→a with the same relation in one transaction.
+    pub fn add_bidirectional_relation(
+        &self,
+        relation_name: &str,
+        node_id1: &str,
+        node_id2: &str,
+        relation: Relation,
+    ) -> Result<()> {
+        let mut txn = self.write_txn()?;
+        self.put_relation(&mut txn, relation_name, node_id1, node_id2, &relation)?;
+        if node_id1 != node_id2 {
+            self.put_relation(&mut txn, relation_name, node_id2, node_id1, &relation)?;
+        }
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    pub fn delete_bidirectional_relation(
+        &self,
+        relation_name: &str,
+        node_id1: &str,
+        node_id2: &str,
+    ) -> Result<()> {
+        let mut txn = self.write_txn()?;
+        self.remove_relation(&mut txn, relation_name, node_id1, node_id2)?;
+        self.remove_relation(&mut txn, relation_name, node_id2, node_id1)?;
         txn.commit().context("Failed to commit transaction")?;
         Ok(())
     }
 
+    // Expired relations still count here until purge_expired_relations
+    // removes them; the counters aren't touched when a relation expires.
+    pub fn degree(&self, node_id: &str) -> Result<(usize, usize)> {
+        let ro_txn = self.read_txn()?;
+        let degree = self.degrees_db.get(&ro_txn, node_id).context("Failed to retrieve degree")?;
+        Ok(degree.unwrap_or((0, 0)))
+    }
+
+    // Weights come from the cached degrees, which include unpurged expired relations
+    pub fn sample_nodes(&self, n: usize, by_degree: bool, seed: u64) -> Result<Vec<String>> {
+        let ro_txn = self.read_txn()?;
+        let mut node_ids = Vec::new();
+        let mut weights = Vec::new();
+        for res in self.node_db.iter(&ro_txn)? {
+            let (node_key, _) = res?;
+            let (in_degree, out_degree) = self.degrees_db
+                .get(&ro_txn, node_key)
+                .context("Failed to retrieve degree")?
+                .unwrap_or((0, 0));
+            node_ids.push(node_key.to_string());
+            weights.push(in_degree + out_degree);
+        }
+
+        if node_ids.is_empty() || n == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Sampled with replacement; key order plus the seed makes output reproducible
+        let mut rng = StdRng::seed_from_u64(seed);
+        let sampled = if by_degree {
+            let index = WeightedIndex::new(&weights)
+                .context("Failed to weight nodes by degree; no node has any relations")?;
+            (0..n).map(|_| node_ids[index.sample(&mut rng)].clone()).collect()
+        } else {
+            (0..n).map(|_| node_ids[rng.gen_range(0..node_ids.len())].clone()).collect()
+        };
+        Ok(sampled)
+    }
+
+    pub fn touch_relation(
+        &self,
+        relation_name: &str,
+        node_id1: &str,
+        node_id2: &str,
+    ) -> Result<Option<Relation>> {
+        let relation_key = encode_relation_key(relation_name, node_id1, node_id2);
+        let mut txn = self.write_txn()?;
+        // An expired relation is as good as gone, like in get_relation
+        let mut relation = match self.relations_db.get(&txn, &relation_key).context("Failed to retrieve relation")? {
+            Some(relation) if !relation.is_expired() => relation,
+            _ => return Ok(None),
+        };
+
+        let mut data: serde_json::Value = if relation.data.trim().is_empty() {
+            serde_json::json!({})
+        } else {
+            serde_json::from_str(&relation.data).context("Relation data is not valid JSON")?
+        };
+        let fields = data.as_object_mut().context("Relation data is not a JSON object")?;
+        let access_count = fields.get("access_count").and_then(|count| count.as_u64()).unwrap_or(0);
+        fields.insert("access_count".to_string(), (access_count + 1).into());
+        relation.data = data.to_string();
+
+        self.put_relation(&mut txn, relation_name, node_id1, node_id2, &relation)?;
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(Some(relation))
+    }
+
     pub fn get_related_nodes(
         &self,
         node_id: &str,
@@ -156,6 +800,40 @@ impl Rhyzome {
         Ok(related_nodes)
     }
 
+    // None when the node itself doesn't exist, as opposed to having no edges.
+    pub fn get_related_checked(&self, node_id: &str, relation_name: &str) -> Result<Option<Vec<String>>> {
+        let ro_txn = self.read_txn()?;
+        if self.node_db.get(&ro_txn, node_id).context("Failed to retrieve node")?.is_none() {
+            return Ok(None);
+        }
+        let related = self.relation_entries(&ro_txn)?
+            .into_iter()
+            .filter(|(name, id1, _, relation)| name == relation_name && id1 == node_id && !relation.is_expired())
+            .map(|(_, _, id2, _)| id2)
+            .collect();
+        Ok(Some(related))
+    }
+
+    pub fn get_incoming_nodes(
+        &self,
+        node_id: &str,
+    ) -> Result<Vec<String>> {
+        let relations = self.query_relations(|(_, _, id2)| id2 == node_id)?;
+        let incoming_nodes: Vec<String> = relations.iter().map(|(_, id1, _)| id1.clone()).collect();
+        Ok(incoming_nodes)
+    }
+
+    // Outgoing neighbors across every relation name, paired with edge weight.
+    pub fn related_with_weights(&self, node_id: &str) -> Result<Vec<(String, f64)>> {
+        let ro_txn = self.read_txn()?;
+        let related = self.relation_entries(&ro_txn)?
+            .into_iter()
+            .filter(|(_, id1, _, relation)| id1 == node_id && !relation.is_expired())
+            .map(|(_, _, id2, relation)| (id2, relation.weight))
+            .collect();
+        Ok(related)
+    }
+
     pub fn dfs(&self, start_node_id: &str) -> Result<Vec<String>> {
         let mut visited: Vec<String> = Vec::new();
         let mut stack: Vec<String> = vec![start_node_id.to_string()];
@@ -176,38 +854,45 @@ impl Rhyzome {
     }
 
     pub fn bfs(&self, start_node_id: &str) -> Result<Vec<String>> {
-        let mut visited: Vec<String> = Vec::new();
-        let mut queue: Vec<String> = vec![start_node_id.to_string()];
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut queue: VecDeque<String> = VecDeque::new();
+        let mut result: Vec<String> = Vec::new();
 
-        while let Some(node_id) = queue.pop(0) {
-            if !visited.contains(&node_id) {
-                visited.push(node_id.clone());
+        visited.insert(start_node_id.to_string());
+        queue.push_back(start_node_id.to_string());
 
-                let relations = self.query_relations(|(_, id1, _)| id1 == &node_id)?;
+        while let Some(node_id) = queue.pop_front() {
+            result.push(node_id.clone());
 
-                for (_, _, id2) in relations {
-                    queue.push(id2);
+            let relations = self.query_relations(|(_, id1, _)| id1 == &node_id)?;
+
+            for (_, _, id2) in relations {
+                if visited.insert(id2.clone()) {
+                    queue.push_back(id2);
                 }
             }
         }
 
-        Ok(visited)
+        Ok(result)
     }
 
     pub fn query_relations<F>(
         &self,
-        filter: F,
+        mut filter: F,
     ) -> Result<Vec<(String, String, String)>>
     where
         F: FnMut(&(String, String, String)) -> bool,
     {
-        let ro_txn = self.env.read_txn().context("Failed to begin read transaction")?;
+        let ro_txn = self.read_txn()?;
         let cursor = self.relations_db.iter(&ro_txn)?;
         let mut result: Vec<(String, String, String)> = Vec::new();
 
         for res in cursor {
-            let ((relation_key, relation), _) = res?;
-            let (relation_name, id1, id2) = parse_relation_key(&relation_key)?;
+            let (relation_key, relation) = res?;
+            if relation.is_expired() {
+                continue;
+            }
+            let (relation_name, id1, id2) = parse_relation_key(relation_key.as_bytes())?;
 
             if filter(&(relation_name.clone(), id1.clone(), id2.clone())) {
                 result.push((relation_name, id1, id2));
@@ -216,39 +901,3437 @@ impl Rhyzome {
 
         Ok(result)
     }
+
+    // Visits live relations in key order until `f` returns false.
+    pub fn for_each_relation<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&str, &str, &str, &Relation) -> Result<bool>,
+    {
+        let ro_txn = self.read_txn()?;
+        for res in self.relations_db.iter(&ro_txn)? {
+            let (relation_key, relation) = res?;
+            if relation.is_expired() {
+                continue;
+            }
+            let (relation_name, id1, id2) = parse_relation_key(relation_key.as_bytes())?;
+            if !f(&relation_name, &id1, &id2, &relation)? {
+                break;
+            }
+        }
+        Ok(())
+    }
     
     pub fn iter_relations(&self) -> Result<Vec<String>> {
-        let ro_txn = self.env.read_txn().context("Failed to begin read transaction")?;
+        let ro_txn = self.read_txn()?;
         let cursor = self.relations_db.iter(&ro_txn)?;
         let mut result: Vec<String> = Vec::new();
 
         for res in cursor {
-            let ((relation_key, _), _) = res?;
-            result.push(relation_key);
+            let (relation_key, _) = res?;
+            result.push(relation_key.to_string());
         }
 
         Ok(result)
     }
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Node {
-    pub id: String,
-    pub data: String,
-    pub timestamp: DateTime<Utc>,
-}
+    pub fn iter_relations_paged(&self, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let ro_txn = self.read_txn()?;
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        let cursor = self.relations_db.range(&ro_txn, &(start, Bound::Unbounded))?;
+        let mut result: Vec<String> = Vec::new();
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Relation {
-    pub data: String,
-    pub timestamp: DateTime<Utc>,
-}
+        for res in cursor.take(limit) {
+            let (relation_key, _) = res?;
+            result.push(relation_key.to_string());
+        }
 
-fn parse_relation_key(relation_key: &[u8]) -> Result<(String, String, String), Box<dyn std::error::Error>> {
-    let relation_key = std::str::from_utf8(relation_key)?;
-    let parts: Vec<&str> = relation_key.split('_').collect();
+        Ok(result)
+    }
+
+    pub fn relation_counts_by_name(&self) -> Result<BTreeMap<String, usize>> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for (name, _, _) in self.query_relations(|_| true)? {
+            *counts.entry(name).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    // Expired relations are skipped like in relation_counts_by_name, so the
+    // degrees are tallied here rather than read from degrees_db, which keeps
+    // counting them until they're purged.
+    pub fn stats(&self) -> Result<GraphStats> {
+        let ro_txn = self.read_txn()?;
+        let node_count = self.node_db.len(&ro_txn).context("Failed to count nodes")? as usize;
+
+        let mut relation_count = 0;
+        let mut degrees: HashMap<String, (usize, usize)> = HashMap::new();
+        for (_, id1, id2, relation) in self.relation_entries(&ro_txn)? {
+            if relation.is_expired() {
+                continue;
+            }
+            relation_count += 1;
+            degrees.entry(id1).or_default().0 += 1;
+            degrees.entry(id2).or_default().1 += 1;
+        }
+        let max_out_degree = degrees.values().map(|(out_degree, _)| *out_degree).max().unwrap_or(0);
+        let max_in_degree = degrees.values().map(|(_, in_degree)| *in_degree).max().unwrap_or(0);
+        let mean_degree = if node_count == 0 { 0.0 } else { relation_count as f64 / node_count as f64 };
+
+        Ok(GraphStats {
+            node_count,
+            relation_count,
+            max_out_degree,
+            max_in_degree,
+            mean_degree,
+        })
+    }
+
+    // Groups nodes whose data is a JSON object with a string `zone` field;
+    // anything else is left out of the stats.
+    pub fn zone_stats(&self) -> Result<BTreeMap<String, ZoneStats>> {
+        let ro_txn = self.read_txn()?;
+        let mut zones: BTreeMap<String, ZoneStats> = BTreeMap::new();
+
+        for res in self.node_db.iter(&ro_txn)? {
+            let (_, node) = res?;
+            let zone = match serde_json::from_str::<serde_json::Value>(&node.data) {
+                Ok(serde_json::Value::Object(fields)) => match fields.get("zone") {
+                    Some(serde_json::Value::String(zone)) => zone.clone(),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+
+            let stats = zones.entry(zone).or_insert(ZoneStats {
+                post_count: 0,
+                latest_timestamp: node.timestamp,
+            });
+            stats.post_count += 1;
+            stats.latest_timestamp = stats.latest_timestamp.max(node.timestamp);
+        }
+
+        Ok(zones)
+    }
+
+    pub fn relation_names(&self) -> Result<Vec<String>> {
+        let relations = self.query_relations(|_| true)?;
+        let names: BTreeSet<String> = relations.into_iter().map(|(name, _, _)| name).collect();
+        Ok(names.into_iter().collect())
+    }
+
+    pub fn clear_node_relations(&self, node_id: &str, direction: Direction) -> Result<usize> {
+        let mut txn = self.write_txn()?;
+        let doomed: Vec<(String, String, String)> = self.relation_entries(&txn)?
+            .into_iter()
+            .filter(|(_, id1, id2, _)| match direction {
+                Direction::Outgoing => id1 == node_id,
+                Direction::Incoming => id2 == node_id,
+                Direction::Both => id1 == node_id || id2 == node_id,
+            })
+            .map(|(name, id1, id2, _)| (name, id1, id2))
+            .collect();
+
+        for (name, id1, id2) in &doomed {
+            self.remove_relation(&mut txn, name, id1, id2)?;
+        }
+
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(doomed.len())
+    }
+
+    pub fn aggregate_into(&self, sources: &[&str], target: &str, relation_name: &str) -> Result<()> {
+        let mut txn = self.write_txn()?;
+        let mut totals: BTreeMap<String, f64> = BTreeMap::new();
+        for (name, id1, id2, relation) in self.relation_entries(&txn)? {
+            if name == relation_name && sources.contains(&id1.as_str()) && !relation.is_expired() {
+                *totals.entry(id2).or_insert(0.0) += relation.weight;
+            }
+        }
+
+        for (neighbor, weight) in totals {
+            let relation = Relation {
+                data: String::new(),
+                timestamp: Utc::now(),
+                weight,
+                expires_at: None,
+            };
+            self.put_relation(&mut txn, relation_name, target, &neighbor, &relation)?;
+        }
+
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    pub fn purge_expired_relations(&self) -> Result<usize> {
+        self.delete_relations_where(|_, _, _, relation| relation.is_expired())
+    }
+
+    pub fn relations_typed<T: DeserializeOwned>(&self, relation_name: &str) -> Result<Vec<TypedRelation<T>>> {
+        let (relations, _) = self.relations_typed_with_errors(relation_name)?;
+        Ok(relations)
+    }
+
+    // Edges whose data doesn't deserialize into T are skipped and reported separately
+    pub fn relations_typed_with_errors<T: DeserializeOwned>(
+        &self,
+        relation_name: &str,
+    ) -> Result<(Vec<TypedRelation<T>>, Vec<RelationDecodeError>)> {
+        let ro_txn = self.read_txn()?;
+        let mut relations = Vec::new();
+        let mut errors = Vec::new();
+
+        for (name, id1, id2, relation) in self.relation_entries(&ro_txn)? {
+            if name != relation_name || relation.is_expired() {
+                continue;
+            }
+            match serde_json::from_str::<T>(&relation.data) {
+                Ok(value) => relations.push((id1, id2, value)),
+                Err(e) => errors.push(RelationDecodeError {
+                    from_id: id1,
+                    to_id: id2,
+                    detail: e.to_string(),
+                }),
+            }
+        }
+
+        Ok((relations, errors))
+    }
+
+    pub fn relations_above_weight(&self, threshold: f64) -> Result<Vec<(String, String, String, f64)>> {
+        let ro_txn = self.read_txn()?;
+        let result = self.relation_entries(&ro_txn)?
+            .into_iter()
+            .filter(|(_, _, _, relation)| relation.weight > threshold && !relation.is_expired())
+            .map(|(name, id1, id2, relation)| (name, id1, id2, relation.weight))
+            .collect();
+        Ok(result)
+    }
+
+    pub fn delete_relations_where<F>(&self, mut filter: F) -> Result<usize>
+    where
+        F: FnMut(&str, &str, &str, &Relation) -> bool,
+    {
+        let mut txn = self.write_txn()?;
+        let doomed: Vec<(String, String, String)> = self.relation_entries(&txn)?
+            .into_iter()
+            .filter(|(name, id1, id2, relation)| filter(name, id1, id2, relation))
+            .map(|(name, id1, id2, _)| (name, id1, id2))
+            .collect();
+
+        for (name, id1, id2) in &doomed {
+            self.remove_relation(&mut txn, name, id1, id2)?;
+        }
+
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(doomed.len())
+    }
+
+    pub fn find_bridges(&self, relation_name: &str) -> Result<Vec<(String, String)>> {
+        // Undirected, so A->B and B->A are one edge, reported in the direction
+        // seen first; self-loops are ignored
+        let mut pairs: HashSet<(String, String)> = HashSet::new();
+        let mut edges: Vec<(String, String)> = Vec::new();
+        for (_, id1, id2) in self.query_relations(|(name, _, _)| name == relation_name)? {
+            if id1 == id2 {
+                continue;
+            }
+            let pair = if id1 < id2 { (id1.clone(), id2.clone()) } else { (id2.clone(), id1.clone()) };
+            if pairs.insert(pair) {
+                edges.push((id1, id2));
+            }
+        }
+
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        let mut ids: Vec<&str> = Vec::new();
+        for (id1, id2) in &edges {
+            for id in [id1.as_str(), id2.as_str()] {
+                if !index.contains_key(id) {
+                    index.insert(id, ids.len());
+                    ids.push(id);
+                }
+            }
+        }
+
+        // Adjacency of (neighbor, edge index) so the edge we arrived by is told apart
+        let mut adjacency: Vec<Vec<(usize, usize)>> = vec![Vec::new(); ids.len()];
+        for (edge, (id1, id2)) in edges.iter().enumerate() {
+            let (a, b) = (index[id1.as_str()], index[id2.as_str()]);
+            adjacency[a].push((b, edge));
+            adjacency[b].push((a, edge));
+        }
+
+        let unvisited = usize::MAX;
+        let mut discovered = vec![unvisited; ids.len()];
+        let mut low = vec![0; ids.len()];
+        let mut timer = 0;
+        let mut bridges = Vec::new();
+
+        for root in 0..ids.len() {
+            if discovered[root] != unvisited {
+                continue;
+            }
+
+            discovered[root] = timer;
+            low[root] = timer;
+            timer += 1;
+            // (node, edge we arrived by, next adjacency slot to look at)
+            let mut stack: Vec<(usize, usize, usize)> = vec![(root, usize::MAX, 0)];
+
+            while let Some(frame) = stack.last_mut() {
+                let (node, parent_edge) = (frame.0, frame.1);
+                if frame.2 < adjacency[node].len() {
+                    let (neighbor, edge) = adjacency[node][frame.2];
+                    frame.2 += 1;
+                    if edge == parent_edge {
+                        continue;
+                    }
+                    if discovered[neighbor] == unvisited {
+                        discovered[neighbor] = timer;
+                        low[neighbor] = timer;
+                        timer += 1;
+                        stack.push((neighbor, edge, 0));
+                    } else {
+                        low[node] = low[node].min(discovered[neighbor]);
+                    }
+                } else {
+                    stack.pop();
+                    if let Some(&(parent, _, _)) = stack.last() {
+                        low[parent] = low[parent].min(low[node]);
+                        if low[node] > discovered[parent] {
+                            bridges.push(edges[parent_edge].clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(bridges)
+    }
+
+    pub fn shortest_path_excluding(
+        &self,
+        from: &str,
+        to: &str,
+        blocked: &HashSet<String>,
+    ) -> Result<Option<Vec<String>>> {
+        if blocked.contains(from) || blocked.contains(to) {
+            return Ok(None);
+        }
+
+        let adjacency = self.full_adjacency()?;
+        let mut parents: HashMap<&str, &str> = HashMap::new();
+        let mut queue: VecDeque<&str> = VecDeque::new();
+        queue.push_back(from);
+
+        while let Some(node_id) = queue.pop_front() {
+            if node_id == to {
+                let mut path = vec![to.to_string()];
+                let mut current = to;
+                while let Some(parent) = parents.get(current) {
+                    path.push(parent.to_string());
+                    current = *parent;
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+
+            for neighbor in adjacency.get(node_id).into_iter().flatten() {
+                let neighbor = neighbor.as_str();
+                if neighbor == from || blocked.contains(neighbor) || parents.contains_key(neighbor) {
+                    continue;
+                }
+                parents.insert(neighbor, node_id);
+                queue.push_back(neighbor);
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Returns None when either endpoint is missing or `to` is unreachable.
+    pub fn shortest_path_detailed(
+        &self,
+        from: &str,
+        to: &str,
+        relation_name: &str,
+    ) -> Result<Option<DetailedPath>> {
+        let ro_txn = self.read_txn()?;
+        let mut adjacency: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, id1, id2, relation) in self.relation_entries(&ro_txn)? {
+            if name == relation_name && !relation.is_expired() {
+                adjacency.entry(id1).or_default().push(id2);
+            }
+        }
+
+        let mut parents: HashMap<&str, &str> = HashMap::new();
+        let mut queue: VecDeque<&str> = VecDeque::new();
+        queue.push_back(from);
+        let mut found = from == to;
+        while let Some(node_id) = queue.pop_front() {
+            if node_id == to {
+                found = true;
+                break;
+            }
+            for neighbor in adjacency.get(node_id).into_iter().flatten() {
+                let neighbor = neighbor.as_str();
+                if neighbor == from || parents.contains_key(neighbor) {
+                    continue;
+                }
+                parents.insert(neighbor, node_id);
+                queue.push_back(neighbor);
+            }
+        }
+        if !found {
+            return Ok(None);
+        }
+
+        let mut ids = vec![to];
+        let mut current = to;
+        while let Some(parent) = parents.get(current) {
+            ids.push(parent);
+            current = *parent;
+        }
+        ids.reverse();
+
+        let mut path = DetailedPath { nodes: Vec::new(), relations: Vec::new() };
+        for node_id in &ids {
+            match self.node_db.get(&ro_txn, node_id).context("Failed to retrieve node")? {
+                Some(node) => path.nodes.push(node),
+                None => return Ok(None),
+            }
+        }
+        for pair in ids.windows(2) {
+            let relation_key = encode_relation_key(relation_name, pair[0], pair[1]);
+            let relation = self.relations_db.get(&ro_txn, &relation_key)
+                .context("Failed to retrieve relation")?
+                .ok_or_else(|| anyhow!("Relation {} vanished mid-read", relation_key))?;
+            path.relations.push(relation);
+        }
+
+        Ok(Some(path))
+    }
+
+    // Replaces the neighbors database with the outgoing lists for one relation.
+    // It is a point-in-time copy: later relation writes are not reflected until
+    // this is called again, and materializing another relation discards the old one.
+    pub fn materialize_neighbors(&self, relation_name: &str) -> Result<()> {
+        let mut txn = self.write_txn()?;
+        let mut adjacency: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (name, id1, id2, relation) in self.relation_entries(&txn)? {
+            if name == relation_name && !relation.is_expired() {
+                adjacency.entry(id1).or_default().push(id2);
+            }
+        }
+
+        self.neighbors_db.clear(&mut txn).context("Failed to clear neighbors")?;
+        for (node_id, neighbors) in &adjacency {
+            self.neighbors_db.put(&mut txn, node_id, neighbors).context("Failed to store neighbors")?;
+        }
+
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(())
+    }
+
+    pub fn get_materialized_neighbors(&self, node_id: &str) -> Result<Option<Vec<String>>> {
+        let ro_txn = self.read_txn()?;
+        let result = self.neighbors_db.get(&ro_txn, node_id).context("Failed to retrieve neighbors")?;
+        Ok(result)
+    }
+
+    pub fn reachable_within_budget(&self, start: &str, relation_name: &str, budget: f64) -> Result<Vec<(String, f64)>> {
+        let adjacency = self.weighted_adjacency(relation_name)?;
+        let mut best: HashMap<String, f64> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        best.insert(start.to_string(), 0.0);
+        heap.push(Candidate { cost: 0.0, node_id: start.to_string() });
+
+        while let Some(Candidate { cost, node_id }) = heap.pop() {
+            if cost > best[&node_id] {
+                continue;
+            }
+            for (neighbor, weight) in adjacency.get(&node_id).into_iter().flatten() {
+                let next = cost + weight;
+                if next > budget {
+                    continue;
+                }
+                if best.get(neighbor).is_none_or(|&known| next < known) {
+                    best.insert(neighbor.clone(), next);
+                    heap.push(Candidate { cost: next, node_id: neighbor.clone() });
+                }
+            }
+        }
+
+        best.remove(start);
+        let mut result: Vec<(String, f64)> = best.into_iter().collect();
+        result.sort_by(|(a_id, a), (b_id, b)| a.total_cmp(b).then_with(|| a_id.cmp(b_id)));
+        Ok(result)
+    }
+
+    pub fn shortest_path(&self, from: &str, to: &str, relation_name: &str) -> Result<Option<(Vec<String>, f64)>> {
+        let adjacency = self.weighted_adjacency(relation_name)?;
+        let mut best: HashMap<String, f64> = HashMap::new();
+        let mut parents: HashMap<String, String> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        best.insert(from.to_string(), 0.0);
+        heap.push(Candidate { cost: 0.0, node_id: from.to_string() });
+
+        while let Some(Candidate { cost, node_id }) = heap.pop() {
+            if node_id == to {
+                let mut path = vec![node_id];
+                while let Some(parent) = parents.get(path.last().unwrap()) {
+                    path.push(parent.clone());
+                }
+                path.reverse();
+                return Ok(Some((path, cost)));
+            }
+            if cost > best[&node_id] {
+                continue;
+            }
+            for (neighbor, weight) in adjacency.get(&node_id).into_iter().flatten() {
+                let next = cost + weight;
+                if best.get(neighbor).is_none_or(|&known| next < known) {
+                    best.insert(neighbor.clone(), next);
+                    parents.insert(neighbor.clone(), node_id.clone());
+                    heap.push(Candidate { cost: next, node_id: neighbor.clone() });
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Returned in BFS order, nearest hops first.
+    pub fn neighbors_within(&self, start: &str, relation_name: &str, hops: usize) -> Result<Vec<String>> {
+        if hops == 0 {
+            return Ok(Vec::new());
+        }
+
+        let adjacency = self.adjacency_list(relation_name)?;
+        let mut visited: HashSet<&str> = HashSet::from([start]);
+        let mut queue: VecDeque<(&str, usize)> = VecDeque::from([(start, 0)]);
+        let mut result: Vec<String> = Vec::new();
+
+        while let Some((node_id, depth)) = queue.pop_front() {
+            if depth == hops {
+                continue;
+            }
+            for neighbor in adjacency.get(node_id).into_iter().flatten() {
+                if visited.insert(neighbor.as_str()) {
+                    result.push(neighbor.clone());
+                    queue.push_back((neighbor, depth + 1));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    // `heuristic` must never overestimate the remaining cost to `to` and must be
+    // consistent (h(a) <= w(a, b) + h(b)); negative estimates are not allowed.
+    // Otherwise the returned path may not be the cheapest. A zero heuristic
+    // makes this behave exactly like shortest_path.
+    pub fn astar<H>(&self, from: &str, to: &str, relation_name: &str, heuristic: H) -> Result<Option<(Vec<String>, f64)>>
+    where
+        H: Fn(&str) -> f64,
+    {
+        let adjacency = self.weighted_adjacency(relation_name)?;
+        let mut best: HashMap<String, f64> = HashMap::new();
+        let mut parents: HashMap<String, String> = HashMap::new();
+        let mut closed: HashSet<String> = HashSet::new();
+        let mut heap = BinaryHeap::new();
+        best.insert(from.to_string(), 0.0);
+        heap.push(Candidate { cost: heuristic(from), node_id: from.to_string() });
+
+        while let Some(Candidate { node_id, .. }) = heap.pop() {
+            if node_id == to {
+                let cost = best[&node_id];
+                let mut path = vec![node_id];
+                while let Some(parent) = parents.get(path.last().unwrap()) {
+                    path.push(parent.clone());
+                }
+                path.reverse();
+                return Ok(Some((path, cost)));
+            }
+            if !closed.insert(node_id.clone()) {
+                continue;
+            }
+            let cost = best[&node_id];
+            for (neighbor, weight) in adjacency.get(&node_id).into_iter().flatten() {
+                let next = cost + weight;
+                if best.get(neighbor).is_none_or(|&known| next < known) {
+                    best.insert(neighbor.clone(), next);
+                    parents.insert(neighbor.clone(), node_id.clone());
+                    heap.push(Candidate { cost: next + heuristic(neighbor), node_id: neighbor.clone() });
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    pub fn eccentricity(&self, node_id: &str) -> Result<usize> {
+        let adjacency = self.full_adjacency()?;
+        Ok(bfs_distances(&adjacency, node_id).into_values().max().unwrap_or(0))
+    }
+
+    pub fn diameter(&self) -> Result<usize> {
+        let adjacency = self.full_adjacency()?;
+        if adjacency.len() > MAX_ALL_PAIRS_NODES {
+            bail!(
+                "Graph has {} nodes, more than the all-pairs limit of {}",
+                adjacency.len(),
+                MAX_ALL_PAIRS_NODES
+            );
+        }
+
+        let mut diameter = 0;
+        for start in adjacency.keys() {
+            let farthest = bfs_distances(&adjacency, start).into_values().max().unwrap_or(0);
+            diameter = diameter.max(farthest);
+        }
+        Ok(diameter)
+    }
+
+    pub fn adjacency_list(&self, relation_name: &str) -> Result<HashMap<String, Vec<String>>> {
+        let mut adjacency: HashMap<String, Vec<String>> = HashMap::new();
+        for (_, id1, id2) in self.query_relations(|(name, _, _)| name == relation_name)? {
+            adjacency.entry(id1).or_default().push(id2);
+        }
+        Ok(adjacency)
+    }
+
+    pub fn has_cycle(&self, relation_name: &str) -> Result<bool> {
+        let adjacency = self.adjacency_list(relation_name)?;
+        // Nodes on the current DFS path; meeting one again is a back edge
+        let mut on_stack: HashSet<&str> = HashSet::new();
+        let mut finished: HashSet<&str> = HashSet::new();
+
+        let mut roots: Vec<&str> = adjacency.keys().map(String::as_str).collect();
+        roots.sort_unstable();
+        for root in roots {
+            if finished.contains(root) {
+                continue;
+            }
+            let mut stack: Vec<(&str, usize)> = vec![(root, 0)];
+            on_stack.insert(root);
+
+            while let Some((node_id, next_child)) = stack.last_mut() {
+                let children = adjacency.get(*node_id).map(Vec::as_slice).unwrap_or(&[]);
+                match children.get(*next_child) {
+                    Some(child) => {
+                        *next_child += 1;
+                        let child = child.as_str();
+                        if on_stack.contains(child) {
+                            return Ok(true);
+                        }
+                        if !finished.contains(child) {
+                            on_stack.insert(child);
+                            stack.push((child, 0));
+                        }
+                    }
+                    None => {
+                        on_stack.remove(*node_id);
+                        finished.insert(*node_id);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    // Kahn's algorithm; ties are broken by id so the order is stable.
+    pub fn topological_order(&self, relation_name: &str) -> Result<Vec<String>> {
+        let adjacency = self.adjacency_list(relation_name)?;
+        let mut in_degrees: BTreeMap<&str, usize> = BTreeMap::new();
+        for (id1, targets) in &adjacency {
+            in_degrees.entry(id1.as_str()).or_default();
+            for id2 in targets {
+                *in_degrees.entry(id2.as_str()).or_default() += 1;
+            }
+        }
+
+        let mut ready: BTreeSet<&str> = in_degrees.iter()
+            .filter(|(_, &in_degree)| in_degree == 0)
+            .map(|(&node_id, _)| node_id)
+            .collect();
+        let mut order: Vec<String> = Vec::with_capacity(in_degrees.len());
+
+        while let Some(node_id) = ready.pop_first() {
+            order.push(node_id.to_string());
+            for id2 in adjacency.get(node_id).into_iter().flatten() {
+                let in_degree = in_degrees.get_mut(id2.as_str()).expect("every target has an in-degree");
+                *in_degree -= 1;
+                if *in_degree == 0 {
+                    ready.insert(id2);
+                }
+            }
+        }
+
+        if order.len() < in_degrees.len() {
+            let stuck = in_degrees.iter()
+                .find(|(_, &in_degree)| in_degree > 0)
+                .map(|(&node_id, _)| node_id)
+                .unwrap_or_default();
+            bail!(
+                "Relation {} has a cycle; {} nodes, including {}, could not be ordered",
+                relation_name,
+                in_degrees.len() - order.len(),
+                stuck
+            );
+        }
+        Ok(order)
+    }
+
+    // Each component is sorted, and components are ordered by their smallest id.
+    pub fn connected_components(&self, relation_name: &str) -> Result<Vec<Vec<String>>> {
+        let mut neighbors: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for node_id in self.iter_nodes()? {
+            neighbors.entry(node_id).or_default();
+        }
+        for (_, id1, id2) in self.query_relations(|(name, _, _)| name == relation_name)? {
+            neighbors.entry(id1.clone()).or_default().push(id2.clone());
+            neighbors.entry(id2).or_default().push(id1);
+        }
+
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut components: Vec<Vec<String>> = Vec::new();
+        for start in neighbors.keys() {
+            if !seen.insert(start.as_str()) {
+                continue;
+            }
+            let mut component = Vec::new();
+            let mut queue: VecDeque<&str> = VecDeque::from([start.as_str()]);
+            while let Some(node_id) = queue.pop_front() {
+                component.push(node_id.to_string());
+                for neighbor in &neighbors[node_id] {
+                    if seen.insert(neighbor.as_str()) {
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+            component.sort();
+            components.push(component);
+        }
+
+        Ok(components)
+    }
+
+    // Power iteration over every stored node plus any relation endpoint. Mass
+    // from nodes without outgoing edges is spread evenly, so scores sum to 1.
+    pub fn pagerank(&self, relation_name: &str, damping: f64, iterations: usize) -> Result<Vec<(String, f64)>> {
+        let adjacency = self.adjacency_list(relation_name)?;
+        let mut ids: BTreeSet<String> = self.iter_nodes()?.into_iter().collect();
+        for (id1, targets) in &adjacency {
+            ids.insert(id1.clone());
+            ids.extend(targets.iter().cloned());
+        }
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<String> = ids.into_iter().collect();
+        let index: HashMap<&str, usize> = ids.iter().enumerate().map(|(i, id)| (id.as_str(), i)).collect();
+        let out_edges: Vec<Vec<usize>> = ids.iter()
+            .map(|id| adjacency.get(id).into_iter().flatten().map(|target| index[target.as_str()]).collect())
+            .collect();
+
+        let n = ids.len() as f64;
+        let mut ranks = vec![1.0 / n; ids.len()];
+        for _ in 0..iterations {
+            let dangling: f64 = ranks.iter()
+                .zip(&out_edges)
+                .filter(|(_, targets)| targets.is_empty())
+                .map(|(rank, _)| rank)
+                .sum();
+            let base = (1.0 - damping) / n + damping * dangling / n;
+            let mut next = vec![base; ids.len()];
+            for (source, targets) in out_edges.iter().enumerate() {
+                let share = damping * ranks[source] / targets.len().max(1) as f64;
+                for &target in targets {
+                    next[target] += share;
+                }
+            }
+            ranks = next;
+        }
+
+        let mut result: Vec<(String, f64)> = ids.into_iter().zip(ranks).collect();
+        result.sort_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then_with(|| a_id.cmp(b_id)));
+        Ok(result)
+    }
+
+    pub fn k_core(&self, k: usize, relation_name: &str) -> Result<Vec<String>> {
+        // Undirected, so A->B and B->A count as one neighbor; self-loops are ignored
+        let mut neighbors: HashMap<String, HashSet<String>> = HashMap::new();
+        for (_, id1, id2) in self.query_relations(|(name, _, _)| name == relation_name)? {
+            if id1 == id2 {
+                continue;
+            }
+            neighbors.entry(id1.clone()).or_default().insert(id2.clone());
+            neighbors.entry(id2).or_default().insert(id1);
+        }
+
+        let mut degrees: HashMap<String, usize> =
+            neighbors.iter().map(|(id, adjacent)| (id.clone(), adjacent.len())).collect();
+        let mut removed: HashSet<String> = HashSet::new();
+        let mut queue: VecDeque<String> =
+            degrees.iter().filter(|(_, degree)| **degree < k).map(|(id, _)| id.clone()).collect();
+
+        while let Some(node_id) = queue.pop_front() {
+            if !removed.insert(node_id.clone()) {
+                continue;
+            }
+            for neighbor in &neighbors[&node_id] {
+                if removed.contains(neighbor) {
+                    continue;
+                }
+                let degree = degrees.get_mut(neighbor).expect("neighbor has a degree");
+                *degree -= 1;
+                if *degree + 1 == k {
+                    queue.push_back(neighbor.clone());
+                }
+            }
+        }
+
+        let mut core: Vec<String> = neighbors.into_keys().filter(|id| !removed.contains(id)).collect();
+        core.sort();
+        Ok(core)
+    }
+
+    // One SimRank iteration from the identity: C * |I(a) ∩ I(b)| / (|I(a)| * |I(b)|)
+    // where I(x) are the in-neighbors of x over the relation.
+    pub fn structural_similarity(&self, a: &str, b: &str, relation_name: &str) -> Result<f64> {
+        if a == b {
+            return Ok(1.0);
+        }
+
+        let mut in_a: HashSet<String> = HashSet::new();
+        let mut in_b: HashSet<String> = HashSet::new();
+        for (_, id1, id2) in self.query_relations(|(name, _, id2)| name == relation_name && (id2 == a || id2 == b))? {
+            if id2 == a {
+                in_a.insert(id1);
+            } else {
+                in_b.insert(id1);
+            }
+        }
+
+        if in_a.is_empty() || in_b.is_empty() {
+            return Ok(0.0);
+        }
+        let shared = in_a.intersection(&in_b).count() as f64;
+        Ok(SIMRANK_DECAY * shared / (in_a.len() * in_b.len()) as f64)
+    }
+
+    pub fn out_degree_all(&self, relation_name: &str) -> Result<HashMap<String, usize>> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (_, id1, _) in self.query_relations(|(name, _, _)| name == relation_name)? {
+            *counts.entry(id1).or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    pub fn transitive_closure(&self, relation_name: &str) -> Result<HashMap<String, HashSet<String>>> {
+        let mut adjacency: HashMap<String, Vec<String>> = HashMap::new();
+        for (_, id1, id2) in self.query_relations(|(name, _, _)| name == relation_name)? {
+            adjacency.entry(id2.clone()).or_default();
+            adjacency.entry(id1).or_default().push(id2);
+        }
+
+        if adjacency.len() > MAX_ALL_PAIRS_NODES {
+            bail!(
+                "Relation {} spans {} nodes, more than the all-pairs limit of {}",
+                relation_name,
+                adjacency.len(),
+                MAX_ALL_PAIRS_NODES
+            );
+        }
+
+        let mut closure = HashMap::new();
+        for start in adjacency.keys() {
+            let mut reachable: HashSet<String> = HashSet::new();
+            let mut stack: Vec<&String> = adjacency[start].iter().collect();
+            while let Some(node_id) = stack.pop() {
+                if reachable.insert(node_id.clone()) {
+                    stack.extend(adjacency[node_id].iter());
+                }
+            }
+            closure.insert(start.clone(), reachable);
+        }
+
+        Ok(closure)
+    }
+
+    // Looks the pair up in a closure from transitive_closure, so repeated checks
+    // on an unchanged graph cost a hash lookup. A node only reaches itself
+    // through a cycle. Recompute the closure after the relation changes.
+    pub fn is_reachable(closure: &HashMap<String, HashSet<String>>, from: &str, to: &str) -> bool {
+        closure.get(from).is_some_and(|reachable| reachable.contains(to))
+    }
+
+    pub fn diff(&self, other: &Rhyzome) -> Result<RhyzomeDiff> {
+        let ro_txn = self.read_txn()?;
+        let other_txn = other.read_txn()?;
+
+        let (nodes_only_in_self, nodes_only_in_other, nodes_changed) =
+            diff_sorted(self.node_db.iter(&ro_txn)?, other.node_db.iter(&other_txn)?)?;
+        let (relations_only_in_self, relations_only_in_other, relations_changed) =
+            diff_sorted(self.relations_db.iter(&ro_txn)?, other.relations_db.iter(&other_txn)?)?;
+
+        Ok(RhyzomeDiff {
+            nodes_only_in_self,
+            nodes_only_in_other,
+            nodes_changed,
+            relations_only_in_self,
+            relations_only_in_other,
+            relations_changed,
+        })
+    }
+
+    pub fn import_edge_list(&self, reader: impl BufRead, relation_name: &str) -> Result<usize> {
+        let mut txn = self.write_txn()?;
+        let mut imported = 0;
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line.context("Failed to read edge list")?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (from_id, to_id, weight) = match fields.as_slice() {
+                [from_id, to_id] => (*from_id, *to_id, default_weight()),
+                [from_id, to_id, weight] => {
+                    let weight = weight
+                        .parse()
+                        .with_context(|| format!("Invalid weight on line {}", line_number + 1))?;
+                    (*from_id, *to_id, weight)
+                }
+                _ => bail!("Expected `from to [weight]` on line {}", line_number + 1),
+            };
+
+            for node_id in [from_id, to_id] {
+                if self.node_db.get(&txn, node_id).context("Failed to retrieve node")?.is_none() {
+                    let node = Node {
+                        id: node_id.to_string(),
+                        data: String::new(),
+                        timestamp: Utc::now(),
+                    };
+                    self.put_node(&mut txn, &node)?;
+                }
+            }
+
+            let relation = Relation {
+                data: String::new(),
+                timestamp: Utc::now(),
+                weight,
+                expires_at: None,
+            };
+            self.put_relation(&mut txn, relation_name, from_id, to_id, &relation)?;
+            imported += 1;
+        }
+
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(imported)
+    }
+
+    pub fn export_ntriples<W: Write>(&self, mut w: W, base_iri: &str) -> Result<usize> {
+        let base_iri = base_iri.trim_end_matches('/');
+        let relations = self.query_relations(|_| true)?;
+
+        for (relation_name, id1, id2) in &relations {
+            writeln!(
+                w,
+                "<{base}/{from}> <{base}/{name}> <{base}/{to}> .",
+                base = base_iri,
+                from = escape_iri_segment(id1),
+                name = escape_iri_segment(relation_name),
+                to = escape_iri_segment(id2),
+            )
+            .context("Failed to write N-Triples line")?;
+        }
+
+        Ok(relations.len())
+    }
+
+    // Streams `{"nodes": [...], "relations": [...]}` one record at a time from a
+    // single read transaction. Expired relations are included; tags are not.
+    pub fn export_json<W: Write>(&self, mut writer: W) -> Result<()> {
+        let ro_txn = self.read_txn()?;
+
+        writer.write_all(b"{\"nodes\":[").context("Failed to write export")?;
+        for (index, res) in self.node_db.iter(&ro_txn)?.enumerate() {
+            let (_, node) = res?;
+            if index > 0 {
+                writer.write_all(b",").context("Failed to write export")?;
+            }
+            serde_json::to_writer(&mut writer, &node).context("Failed to write node")?;
+        }
+
+        writer.write_all(b"],\"relations\":[").context("Failed to write export")?;
+        for (index, res) in self.relations_db.iter(&ro_txn)?.enumerate() {
+            let (relation_key, relation) = res?;
+            let (name, from_id, to_id) = parse_relation_key(relation_key.as_bytes())?;
+            if index > 0 {
+                writer.write_all(b",").context("Failed to write export")?;
+            }
+            let record = ExportedRelation { name, from_id, to_id, relation };
+            serde_json::to_writer(&mut writer, &record).context("Failed to write relation")?;
+        }
+
+        writer.write_all(b"]}").context("Failed to write export")?;
+        writer.flush().context("Failed to flush export")?;
+        Ok(())
+    }
+
+    // Loads an export_json dump in one transaction. Refuses a non-empty store
+    // so the result is exactly the exported graph.
+    pub fn import_json<R: Read>(&self, reader: R) -> Result<()> {
+        let dump: ExportedGraph = serde_json::from_reader(BufReader::new(reader)).context("Failed to parse export")?;
+
+        let mut txn = self.write_txn()?;
+        if !self.node_db.is_empty(&txn)? || !self.relations_db.is_empty(&txn)? {
+            bail!("Refusing to import into a non-empty store");
+        }
+        for node in &dump.nodes {
+            self.put_node(&mut txn, node)?;
+        }
+        for record in &dump.relations {
+            self.put_relation(&mut txn, &record.name, &record.from_id, &record.to_id, &record.relation)?;
+        }
+        txn.commit().context("Failed to commit import")?;
+        Ok(())
+    }
+
+    // Directed GraphML with node data and edge relation name and weight. Edge
+    // endpoints missing from node_db are emitted as nodes without data so the
+    // document stays valid. Expired relations are left out.
+    pub fn export_graphml<W: Write>(&self, mut writer: W) -> Result<()> {
+        let ro_txn = self.read_txn()?;
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(writer, r#"  <key id="data" for="node" attr.name="data" attr.type="string"/>"#)?;
+        writeln!(writer, r#"  <key id="relation" for="edge" attr.name="relation" attr.type="string"/>"#)?;
+        writeln!(writer, r#"  <key id="weight" for="edge" attr.name="weight" attr.type="double"/>"#)?;
+        writeln!(writer, r#"  <graph id="rhyzome" edgedefault="directed">"#)?;
+
+        let mut written: HashSet<String> = HashSet::new();
+        for res in self.node_db.iter(&ro_txn)? {
+            let (node_key, node) = res?;
+            writeln!(
+                writer,
+                r#"    <node id="{}"><data key="data">{}</data></node>"#,
+                escape_xml(node_key),
+                escape_xml(&node.data)
+            )?;
+            written.insert(node_key.to_string());
+        }
+
+        let relations: Vec<_> = self.relation_entries(&ro_txn)?
+            .into_iter()
+            .filter(|(_, _, _, relation)| !relation.is_expired())
+            .collect();
+        for (_, id1, id2, _) in &relations {
+            for node_id in [id1, id2] {
+                if written.insert(node_id.clone()) {
+                    writeln!(writer, r#"    <node id="{}"/>"#, escape_xml(node_id))?;
+                }
+            }
+        }
+
+        for (index, (name, id1, id2, relation)) in relations.iter().enumerate() {
+            writeln!(
+                writer,
+                r#"    <edge id="e{}" source="{}" target="{}"><data key="relation">{}</data><data key="weight">{}</data></edge>"#,
+                index,
+                escape_xml(id1),
+                escape_xml(id2),
+                escape_xml(name),
+                relation.weight
+            )?;
+        }
+
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")?;
+        writer.flush().context("Failed to flush GraphML")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet(&self, nodes_path: &Path, relations_path: &Path) -> Result<()> {
+        use arrow::array::{ArrayRef, Float64Array, StringArray, TimestampMillisecondArray};
+        use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+
+        let ro_txn = self.read_txn()?;
+        let timestamp_type = DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()));
+
+        let (mut ids, mut data, mut timestamps) = (Vec::new(), Vec::new(), Vec::new());
+        for res in self.node_db.iter(&ro_txn)? {
+            let (_, node) = res?;
+            ids.push(node.id);
+            data.push(node.data);
+            timestamps.push(node.timestamp.timestamp_millis());
+        }
+        let node_schema = Schema::new(vec![
+            Field::new("id", DataType::Utf8, false),
+            Field::new("data", DataType::Utf8, false),
+            Field::new("timestamp", timestamp_type.clone(), false),
+        ]);
+        let node_columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(ids)),
+            Arc::new(StringArray::from(data)),
+            Arc::new(TimestampMillisecondArray::from(timestamps).with_timezone("UTC")),
+        ];
+        write_parquet(nodes_path, node_schema, node_columns).context("Failed to write nodes parquet")?;
+
+        let (mut names, mut from_ids, mut to_ids) = (Vec::new(), Vec::new(), Vec::new());
+        let (mut data, mut timestamps, mut weights) = (Vec::new(), Vec::new(), Vec::new());
+        for (name, id1, id2, relation) in self.relation_entries(&ro_txn)? {
+            names.push(name);
+            from_ids.push(id1);
+            to_ids.push(id2);
+            data.push(relation.data);
+            timestamps.push(relation.timestamp.timestamp_millis());
+            weights.push(relation.weight);
+        }
+        let relation_schema = Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("from_id", DataType::Utf8, false),
+            Field::new("to_id", DataType::Utf8, false),
+            Field::new("data", DataType::Utf8, false),
+            Field::new("timestamp", timestamp_type, false),
+            Field::new("weight", DataType::Float64, false),
+        ]);
+        let relation_columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(names)),
+            Arc::new(StringArray::from(from_ids)),
+            Arc::new(StringArray::from(to_ids)),
+            Arc::new(StringArray::from(data)),
+            Arc::new(TimestampMillisecondArray::from(timestamps).with_timezone("UTC")),
+            Arc::new(Float64Array::from(weights)),
+        ];
+        write_parquet(relations_path, relation_schema, relation_columns)
+            .context("Failed to write relations parquet")?;
+
+        Ok(())
+    }
+
+    // The cost-ordered searches built on this never terminate on a negative
+    // cycle, so negative and NaN weights are rejected rather than searched.
+    fn weighted_adjacency(&self, relation_name: &str) -> Result<HashMap<String, Vec<(String, f64)>>> {
+        let ro_txn = self.read_txn()?;
+        let mut adjacency: HashMap<String, Vec<(String, f64)>> = HashMap::new();
+        for (name, id1, id2, relation) in self.relation_entries(&ro_txn)? {
+            if name == relation_name && !relation.is_expired() {
+                if relation.weight.is_nan() || relation.weight < 0.0 {
+                    bail!(
+                        "Relation {} from {} to {} has weight {}; weights must be non-negative",
+                        name,
+                        id1,
+                        id2,
+                        relation.weight
+                    );
+                }
+                adjacency.entry(id1).or_default().push((id2, relation.weight));
+            }
+        }
+        Ok(adjacency)
+    }
+
+    fn full_adjacency(&self) -> Result<HashMap<String, Vec<String>>> {
+        let mut adjacency: HashMap<String, Vec<String>> = HashMap::new();
+        for (_, id1, id2) in self.query_relations(|_| true)? {
+            adjacency.entry(id2.clone()).or_default();
+            adjacency.entry(id1).or_default().push(id2);
+        }
+        Ok(adjacency)
+    }
+
+    pub fn check_integrity(&self) -> Result<IntegrityReport> {
+        let ro_txn = self.read_txn()?;
+        let mut report = IntegrityReport::default();
+
+        let mut node_ids: HashSet<String> = HashSet::new();
+        for res in self.node_db.iter(&ro_txn)? {
+            let (node_key, node) = res?;
+            if node.id != node_key {
+                report.mismatched_node_keys.push(node_key.to_string());
+            }
+            node_ids.insert(node_key.to_string());
+        }
+
+        let mut expected_degrees: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut seen_triples: HashMap<(String, String, String), String> = HashMap::new();
+        for res in self.relations_db.iter(&ro_txn)? {
+            let (relation_key, _) = res?;
+            let triple = match parse_relation_key(relation_key.as_bytes()) {
+                Ok(triple) => triple,
+                Err(_) => {
+                    report.unparseable_relation_keys.push(relation_key.to_string());
+                    // An unmigrated legacy key is still a real relation, so it
+                    // counts toward degrees and duplicates when it splits one way
+                    match self.split_legacy_relation_key(&ro_txn, relation_key)? {
+                        Some(triple) => triple,
+                        None => continue,
+                    }
+                }
+            };
+
+            let (_, id1, id2) = &triple;
+            if !node_ids.contains(id1) || !node_ids.contains(id2) {
+                report.dangling_relations.push(relation_key.to_string());
+            }
+            // Duplicates collapse into one key on migration, so count them once
+            if let Some(first_key) = seen_triples.get(&triple) {
+                report.duplicate_relations.push((first_key.clone(), relation_key.to_string()));
+            } else {
+                expected_degrees.entry(id1.clone()).or_default().0 += 1;
+                expected_degrees.entry(id2.clone()).or_default().1 += 1;
+                seen_triples.insert(triple, relation_key.to_string());
+            }
+        }
+
+        for res in self.degrees_db.iter(&ro_txn)? {
+            let (node_id, cached) = res?;
+            let expected = expected_degrees.remove(node_id).unwrap_or((0, 0));
+            if cached != expected {
+                report.degree_mismatches.push(node_id.to_string());
+            }
+        }
+        // Whatever is left has relations but no cached degree at all
+        report.degree_mismatches.extend(expected_degrees.into_keys());
+
+        for res in self.tags_db.iter(&ro_txn)? {
+            let (tag, tagged) = res?;
+            for node_id in tagged {
+                if !node_ids.contains(&node_id) {
+                    report.orphan_tags.push((tag.to_string(), node_id));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    // Rewrites legacy keys in place. Keys that split more than one way, or
+    // whose escaped form is already taken, are left as they are and reported.
+    pub fn migrate_relation_keys(&self) -> Result<MigrationReport> {
+        let mut txn = self.write_txn()?;
+        let mut report = MigrationReport::default();
+        let mut rewrites: Vec<(String, (String, String, String), Relation)> = Vec::new();
+
+        for res in self.relations_db.iter(&txn)? {
+            let (relation_key, relation) = res?;
+            // Keys that already parse under the escaped scheme are left alone
+            if parse_relation_key(relation_key.as_bytes()).is_ok() {
+                continue;
+            }
+            match self.split_legacy_relation_key(&txn, relation_key)? {
+                Some(parts) => rewrites.push((relation_key.to_string(), parts, relation)),
+                None => report.ambiguous_keys.push(relation_key.to_string()),
+            }
+        }
+
+        for (old_key, (relation_name, node_id1, node_id2), relation) in rewrites {
+            let new_key = encode_relation_key(&relation_name, &node_id1, &node_id2);
+            if self.relations_db.get(&txn, &new_key).context("Failed to retrieve relation")?.is_some() {
+                report.conflicting_keys.push(old_key);
+                continue;
+            }
+            // The degree counters already include this edge, so only the key
+            // moves; the RelationPut lets changelog readers see the new key.
+            self.relations_db.delete(&mut txn, &old_key).context("Failed to delete legacy relation")?;
+            self.relations_db.put(&mut txn, &new_key, &relation).context("Failed to put migrated relation")?;
+            self.record_change(
+                &mut txn,
+                Change::RelationPut {
+                    name: relation_name,
+                    from_id: node_id1,
+                    to_id: node_id2,
+                },
+            );
+            report.migrated += 1;
+        }
+
+        txn.commit().context("Failed to commit transaction")?;
+        Ok(report)
+    }
+
+    // A legacy `name_id1_id2` key with extra underscores could split several
+    // ways; only accept the split whose two ids both exist as nodes.
+    fn split_legacy_relation_key(&self, txn: &RoTxn, relation_key: &str) -> Result<Option<(String, String, String)>> {
+        let parts: Vec<&str> = relation_key.split('_').collect();
+        if parts.len() < 3 {
+            return Ok(None);
+        }
+        if parts.len() == 3 {
+            return Ok(Some((parts[0].to_string(), parts[1].to_string(), parts[2].to_string())));
+        }
+
+        let mut candidates = Vec::new();
+        for i in 1..parts.len() - 1 {
+            for j in i + 1..parts.len() {
+                let node_id1 = parts[i..j].join("_");
+                let node_id2 = parts[j..].join("_");
+                let known = self.node_db.get(txn, &node_id1)?.is_some()
+                    && self.node_db.get(txn, &node_id2)?.is_some();
+                if known {
+                    candidates.push((parts[..i].join("_"), node_id1, node_id2));
+                }
+            }
+        }
+
+        Ok(if candidates.len() == 1 { candidates.pop() } else { None })
+    }
+
+    fn relation_entries(&self, txn: &RoTxn) -> Result<Vec<RelationEntry>> {
+        let cursor = self.relations_db.iter(txn)?;
+        let mut result = Vec::new();
+
+        for res in cursor {
+            let (relation_key, relation) = res?;
+            let (relation_name, id1, id2) = parse_relation_key(relation_key.as_bytes())?;
+            result.push((relation_name, id1, id2, relation));
+        }
+
+        Ok(result)
+    }
+
+    // Node and relation writes go through these helpers so the changelog (and,
+    // for relations, the degree counters) stay in step inside the caller's txn.
+    fn put_node(&self, txn: &mut WriteTxn, node: &Node) -> Result<()> {
+        self.node_db.put(txn, &node.id, node).context("Failed to put node")?;
+        self.record_change(txn, Change::NodePut { id: node.id.clone() });
+        Ok(())
+    }
+
+    fn remove_node(&self, txn: &mut WriteTxn, node_id: &str) -> Result<bool> {
+        let existed = self.node_db.delete(txn, node_id).context("Failed to delete node")?;
+        self.untag_node(txn, node_id)?;
+        if existed {
+            self.record_change(txn, Change::NodeDeleted { id: node_id.to_string() });
+        }
+        Ok(existed)
+    }
+
+    fn put_relation(
+        &self,
+        txn: &mut WriteTxn,
+        relation_name: &str,
+        node_id1: &str,
+        node_id2: &str,
+        relation: &Relation,
+    ) -> Result<()> {
+        if let Some(validator) = self.relation_validators.get(relation_name) {
+            validator(relation).with_context(|| format!("Relation {} rejected by validator", relation_name))?;
+        }
+
+        let relation_key = encode_relation_key(relation_name, node_id1, node_id2);
+        let is_new = self.relations_db.get(txn, &relation_key)
+            .context("Failed to retrieve relation")?
+            .is_none();
+        self.relations_db.put(txn, &relation_key, relation).context("Failed to put relation")?;
+        if is_new {
+            self.adjust_degrees(txn, node_id1, node_id2, 1)?;
+        }
+        self.record_change(
+            txn,
+            Change::RelationPut {
+                name: relation_name.to_string(),
+                from_id: node_id1.to_string(),
+                to_id: node_id2.to_string(),
+            },
+        );
+        Ok(())
+    }
+
+    fn remove_relation(
+        &self,
+        txn: &mut WriteTxn,
+        relation_name: &str,
+        node_id1: &str,
+        node_id2: &str,
+    ) -> Result<bool> {
+        let relation_key = encode_relation_key(relation_name, node_id1, node_id2);
+        let existed = self.relations_db.delete(txn, &relation_key).context("Failed to delete relation")?;
+        if existed {
+            self.adjust_degrees(txn, node_id1, node_id2, -1)?;
+            self.record_change(
+                txn,
+                Change::RelationDeleted {
+                    name: relation_name.to_string(),
+                    from_id: node_id1.to_string(),
+                    to_id: node_id2.to_string(),
+                },
+            );
+        }
+        Ok(existed)
+    }
+
+    // Entries are written (or buffered) when the txn commits; an aborted txn
+    // just leaves a gap in the sequence.
+    fn record_change(&self, txn: &mut WriteTxn, change: Change) {
+        let entry = ChangeEntry {
+            seq: self.changelog.next_seq.fetch_add(1, AtomicOrdering::SeqCst),
+            timestamp: Utc::now(),
+            change,
+        };
+        txn.pending.push(entry);
+    }
+
+    fn untag_node(&self, txn: &mut RwTxn, node_id: &str) -> Result<()> {
+        let mut tagged: Vec<(String, BTreeSet<String>)> = Vec::new();
+        for res in self.tags_db.iter(txn)? {
+            let (tag, node_ids) = res?;
+            if node_ids.contains(node_id) {
+                tagged.push((tag.to_string(), node_ids));
+            }
+        }
+
+        for (tag, mut node_ids) in tagged {
+            node_ids.remove(node_id);
+            self.put_tag(txn, &tag, &node_ids)?;
+        }
+        Ok(())
+    }
+
+    fn put_tag(&self, txn: &mut RwTxn, tag: &str, node_ids: &BTreeSet<String>) -> Result<()> {
+        if node_ids.is_empty() {
+            self.tags_db.delete(txn, tag).context("Failed to delete tag")?;
+        } else {
+            self.tags_db.put(txn, tag, node_ids).context("Failed to update tag")?;
+        }
+        Ok(())
+    }
+
+    fn adjust_degrees(&self, txn: &mut RwTxn, from_id: &str, to_id: &str, delta: isize) -> Result<()> {
+        let (out_degree, in_degree) = self.degrees_db.get(txn, from_id)
+            .context("Failed to retrieve degree")?
+            .unwrap_or((0, 0));
+        self.put_degree(txn, from_id, (out_degree.saturating_add_signed(delta), in_degree))?;
+
+        let (out_degree, in_degree) = self.degrees_db.get(txn, to_id)
+            .context("Failed to retrieve degree")?
+            .unwrap_or((0, 0));
+        self.put_degree(txn, to_id, (out_degree, in_degree.saturating_add_signed(delta)))
+    }
+
+    fn put_degree(&self, txn: &mut RwTxn, node_id: &str, degree: (usize, usize)) -> Result<()> {
+        if degree == (0, 0) {
+            self.degrees_db.delete(txn, node_id).context("Failed to delete degree")?;
+        } else {
+            self.degrees_db.put(txn, node_id, &degree).context("Failed to update degree")?;
+        }
+        Ok(())
+    }
+}
+
+// Every handle shares one environment, gate and changelog.
+impl Clone for Rhyzome {
+    fn clone(&self) -> Self {
+        Rhyzome {
+            node_db: self.node_db,
+            relations_db: self.relations_db,
+            degrees_db: self.degrees_db,
+            tags_db: self.tags_db,
+            neighbors_db: self.neighbors_db,
+            env: self.env.clone(),
+            txn_gate: Arc::clone(&self.txn_gate),
+            write_lock: Arc::clone(&self.write_lock),
+            relation_validators: self.relation_validators.clone(),
+            changelog: Arc::clone(&self.changelog),
+        }
+    }
+}
+
+impl Drop for Rhyzome {
+    fn drop(&mut self) {
+        // Only the last handle flushes; earlier drops would cut batches short
+        if self.changelog.batching.is_some() && Arc::strong_count(&self.changelog) == 1 {
+            if let Err(e) = self.flush_changelog() {
+                eprintln!("Failed to flush changelog on drop: {:?}", e);
+            }
+        }
+    }
+}
+
+// Holds one read transaction open so every read sees the same state. Keep it
+// short-lived: while it exists the map cannot be auto-grown.
+pub struct Snapshot<'a> {
+    rhyzome: &'a Rhyzome,
+    txn: Gated<'a, RoTxn<'a>>,
+}
+
+impl<'a> Snapshot<'a> {
+    // Fails with RhyzomeError::Corrupt on an undecodable value, like Rhyzome::get_node
+    pub fn get_node(&self, node_id: &str) -> Result<Option<Node>> {
+        self.rhyzome.decode_node(&self.txn, node_id)
+    }
+
+    pub fn get_relation(&self, relation_name: &str, node_id1: &str, node_id2: &str) -> Result<Option<Relation>> {
+        let relation_key = encode_relation_key(relation_name, node_id1, node_id2);
+        let result = self.rhyzome.relations_db.get(&self.txn, &relation_key).context("Failed to retrieve relation")?;
+        Ok(result.filter(|relation| !relation.is_expired()))
+    }
+
+    pub fn get_related_nodes(&self, node_id: &str) -> Result<Vec<String>> {
+        let related_nodes = self.rhyzome.relation_entries(&self.txn)?
+            .into_iter()
+            .filter(|(_, id1, _, relation)| id1 == node_id && !relation.is_expired())
+            .map(|(_, _, id2, _)| id2)
+            .collect();
+        Ok(related_nodes)
+    }
+
+    pub fn iter_nodes(&self) -> Result<Vec<String>> {
+        let mut result: Vec<String> = Vec::new();
+        for res in self.rhyzome.node_db.iter(&self.txn)? {
+            let (node_key, _) = res?;
+            result.push(node_key.to_string());
+        }
+        Ok(result)
+    }
+
+    // Like Rhyzome::degree, this still counts unpurged expired relations
+    pub fn degree(&self, node_id: &str) -> Result<(usize, usize)> {
+        let degree = self.rhyzome.degrees_db.get(&self.txn, node_id).context("Failed to retrieve degree")?;
+        Ok(degree.unwrap_or((0, 0)))
+    }
+}
+
+// Async front for the heed store. Database work runs on a dedicated, bounded
+// pool rather than tokio's blocking pool, so at most `threads` operations are
+// in flight and heed calls can't crowd out the runtime's own blocking tasks.
+#[derive(Clone)]
+pub struct AsyncRhyzome {
+    rhyzome: Arc<Rhyzome>,
+    pool: Arc<rayon::ThreadPool>,
+}
+
+impl AsyncRhyzome {
+    pub fn new(rhyzome: Rhyzome, threads: usize) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.max(1))
+            .thread_name(|index| format!("rhyzome-heed-{}", index))
+            // Without a handler rayon aborts the process on panic; we surface it as an error
+            .panic_handler(|_| eprintln!("Heed task panicked"))
+            .build()
+            .context("Failed to build heed thread pool")?;
+        Ok(AsyncRhyzome {
+            rhyzome: Arc::new(rhyzome),
+            pool: Arc::new(pool),
+        })
+    }
+
+    pub fn inner(&self) -> &Rhyzome {
+        &self.rhyzome
+    }
+
+    pub fn threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    pub async fn run<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&Rhyzome) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let rhyzome = Arc::clone(&self.rhyzome);
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        self.pool.spawn(move || {
+            let _ = sender.send(f(&rhyzome));
+        });
+        receiver.await.context("Heed task ended without a result")?
+    }
+
+    pub async fn get_node(&self, node_id: &str) -> Result<Option<Node>> {
+        let node_id = node_id.to_string();
+        self.run(move |rhyzome| rhyzome.get_node(&node_id)).await
+    }
+
+    pub async fn add_node(&self, node: Node) -> Result<()> {
+        self.run(move |rhyzome| rhyzome.add_node(node)).await
+    }
+
+    pub async fn delete_node(&self, node_id: &str) -> Result<bool> {
+        let node_id = node_id.to_string();
+        self.run(move |rhyzome| rhyzome.delete_node(&node_id)).await
+    }
+
+    pub async fn get_relation(&self, relation_name: &str, node_id1: &str, node_id2: &str) -> Result<Option<Relation>> {
+        let (relation_name, node_id1, node_id2) = (relation_name.to_string(), node_id1.to_string(), node_id2.to_string());
+        self.run(move |rhyzome| rhyzome.get_relation(&relation_name, &node_id1, &node_id2)).await
+    }
+
+    pub async fn add_relation(&self, relation_name: &str, node_id1: &str, node_id2: &str, relation: Relation) -> Result<()> {
+        let (relation_name, node_id1, node_id2) = (relation_name.to_string(), node_id1.to_string(), node_id2.to_string());
+        self.run(move |rhyzome| rhyzome.add_relation(&relation_name, &node_id1, &node_id2, relation)).await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    NodePut { id: String },
+    NodeDeleted { id: String },
+    RelationPut { name: String, from_id: String, to_id: String },
+    RelationDeleted { name: String, from_id: String, to_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangeEntry {
+    pub seq: u64,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+// Min-heap entry for Dijkstra-style searches over f64 costs
+struct Candidate {
+    cost: f64,
+    node_id: String,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so BinaryHeap pops the cheapest first
+        other.cost.total_cmp(&self.cost).then_with(|| other.node_id.cmp(&self.node_id))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Outgoing,
+    Incoming,
+    Both,
+}
+
+#[derive(Debug, Clone)]
+pub struct AutoGrow {
+    /// Grow once less than this percentage of the map is free.
+    pub min_free_percent: f64,
+    /// New map size as a multiple of the current one.
+    pub growth_factor: f64,
+    pub check_interval: Duration,
+}
+
+impl Default for AutoGrow {
+    fn default() -> Self {
+        AutoGrow {
+            min_free_percent: 20.0,
+            growth_factor: 2.0,
+            check_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChangelogBatching {
+    /// Flush once this many entries are waiting.
+    pub max_entries: usize,
+    pub flush_interval: Duration,
+}
+
+struct Changelog {
+    db: Database<Str, SerdeJson<ChangeEntry>>,
+    sender: broadcast::Sender<ChangeEntry>,
+    next_seq: AtomicU64,
+    batching: Option<ChangelogBatching>,
+    // Committed entries not yet written to db; only used when batching
+    buffer: Mutex<Vec<ChangeEntry>>,
+}
+
+// A transaction paired with a shared hold on the gate (and the writer slot for
+// write transactions), both released after the txn ends. Write transactions
+// also carry the changes they recorded, persisted and broadcast on commit.
+struct Gated<'a, T> {
+    txn: T,
+    _gate: RwLockReadGuard<'a, ()>,
+    _writer: Option<MutexGuard<'a, ()>>,
+    changelog: Option<&'a Changelog>,
+    pending: Vec<ChangeEntry>,
+}
+
+impl<'a, T> Deref for Gated<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.txn
+    }
+}
+
+impl<'a, T> DerefMut for Gated<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.txn
+    }
+}
+
+impl<'a> Gated<'a, RwTxn<'a>> {
+    fn commit(self) -> Result<()> {
+        let Gated { mut txn, _gate, _writer, changelog, pending } = self;
+        let changelog = match changelog {
+            Some(changelog) => changelog,
+            None => return Ok(txn.commit()?),
+        };
+
+        match &changelog.batching {
+            None => {
+                for entry in &pending {
+                    changelog.db.put(&mut txn, &changelog_key(entry.seq), entry)?;
+                }
+                txn.commit()?;
+            }
+            Some(batching) => {
+                let mut buffer = changelog.buffer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                if buffer.len() + pending.len() >= batching.max_entries {
+                    for entry in buffer.iter().chain(&pending) {
+                        changelog.db.put(&mut txn, &changelog_key(entry.seq), entry)?;
+                    }
+                    txn.commit()?;
+                    buffer.clear();
+                } else {
+                    txn.commit()?;
+                    buffer.extend(pending.iter().cloned());
+                }
+            }
+        }
+
+        for entry in pending {
+            // No subscribers is fine; the changelog keeps the entry regardless
+            let _ = changelog.sender.send(entry);
+        }
+        Ok(())
+    }
+}
+
+fn flush_changelog_buffer(
+    env: &heed::Env,
+    txn_gate: &RwLock<()>,
+    write_lock: &Mutex<()>,
+    changelog: &Changelog,
+) -> Result<usize> {
+    // Same lock order as a regular write: writer slot, gate, then buffer
+    let _writer = write_lock.lock().map_err(|_| anyhow!("Write lock poisoned"))?;
+    let _gate = txn_gate.read().map_err(|_| anyhow!("Transaction gate poisoned"))?;
+    let mut buffer = changelog.buffer.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if buffer.is_empty() {
+        return Ok(0);
+    }
+
+    let mut txn = env.write_txn().context("Failed to begin write transaction")?;
+    for entry in buffer.iter() {
+        changelog.db.put(&mut txn, &changelog_key(entry.seq), entry).context("Failed to append changelog")?;
+    }
+    txn.commit().context("Failed to commit transaction")?;
+
+    let flushed = buffer.len();
+    buffer.clear();
+    Ok(flushed)
+}
+
+fn grow_map_if_needed(env: &heed::Env, txn_gate: &RwLock<()>, config: &AutoGrow) -> Result<bool> {
+    // Never wait for the gate: the lock favours writers, so a thread already
+    // holding a transaction (say, a Snapshot) that opens another would queue
+    // behind us and deadlock. A busy gate just defers growing to the next check.
+    let _exclusive = match txn_gate.try_write() {
+        Ok(guard) => guard,
+        Err(TryLockError::WouldBlock) => return Ok(false),
+        Err(TryLockError::Poisoned(_)) => bail!("Transaction gate poisoned"),
+    };
+
+    let map_size = env.info().map_size;
+    let used = env.non_free_pages_size().context("Failed to read used map size")? as usize;
+    let free_percent = 100.0 * map_size.saturating_sub(used) as f64 / map_size as f64;
+    if free_percent >= config.min_free_percent {
+        return Ok(false);
+    }
+
+    let new_size = (map_size as f64 * config.growth_factor) as usize;
+    // Safe because holding the gate exclusively means no transaction is open
+    // in this process, which is what mdb_env_set_mapsize requires.
+    unsafe { env.resize(new_size) }.context("Failed to resize map")?;
+    Ok(true)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RhyzomeError {
+    Corrupt { id: String, detail: String },
+}
+
+impl std::fmt::Display for RhyzomeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RhyzomeError::Corrupt { id, detail } => write!(f, "Node {} is corrupt: {}", id, detail),
+        }
+    }
+}
+
+impl std::error::Error for RhyzomeError {}
+
+#[derive(Debug, Clone)]
+pub struct RelationDecodeError {
+    pub from_id: String,
+    pub to_id: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphStats {
+    pub node_count: usize,
+    pub relation_count: usize,
+    pub max_out_degree: usize,
+    pub max_in_degree: usize,
+    pub mean_degree: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneStats {
+    pub post_count: usize,
+    pub latest_timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedRelation {
+    name: String,
+    from_id: String,
+    to_id: String,
+    #[serde(flatten)]
+    relation: Relation,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportedGraph {
+    nodes: Vec<Node>,
+    relations: Vec<ExportedRelation>,
+}
+
+// relations[i] links nodes[i] to nodes[i + 1].
+#[derive(Debug, Serialize)]
+pub struct DetailedPath {
+    pub nodes: Vec<Node>,
+    pub relations: Vec<Relation>,
+}
+
+#[derive(Debug, Default)]
+pub struct IntegrityReport {
+    /// Relation keys whose source or target node is missing.
+    pub dangling_relations: Vec<String>,
+    /// Relation keys that don't decode, e.g. unmigrated legacy keys.
+    pub unparseable_relation_keys: Vec<String>,
+    /// Pairs of relation keys naming the same (name, from, to): an unmigrated
+    /// legacy key alongside its escaped form.
+    pub duplicate_relations: Vec<(String, String)>,
+    /// Node keys whose stored node carries a different id.
+    pub mismatched_node_keys: Vec<String>,
+    /// Node ids whose cached degree disagrees with relations_db.
+    pub degree_mismatches: Vec<String>,
+    /// (tag, node id) entries pointing at deleted nodes.
+    pub orphan_tags: Vec<(String, String)>,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        self.dangling_relations.is_empty()
+            && self.unparseable_relation_keys.is_empty()
+            && self.duplicate_relations.is_empty()
+            && self.mismatched_node_keys.is_empty()
+            && self.degree_mismatches.is_empty()
+            && self.orphan_tags.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RhyzomeDiff {
+    pub nodes_only_in_self: Vec<String>,
+    pub nodes_only_in_other: Vec<String>,
+    pub nodes_changed: Vec<String>,
+    pub relations_only_in_self: Vec<String>,
+    pub relations_only_in_other: Vec<String>,
+    pub relations_changed: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    /// Legacy keys rewritten under the escaped scheme.
+    pub migrated: usize,
+    /// Legacy keys that split into more than one existing (from, to) pair.
+    pub ambiguous_keys: Vec<String>,
+    /// Legacy keys left alone because their escaped form already exists.
+    pub conflicting_keys: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Node {
+    pub id: String,
+    pub data: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Relation {
+    pub data: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl Node {
+    pub fn new(id: impl Into<String>, data: impl Into<String>) -> Self {
+        Node {
+            id: id.into(),
+            data: data.into(),
+            timestamp: Utc::now(),
+        }
+    }
+}
+
+impl Relation {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+// Relation keys are `name_id1_id2` with `%` and `_` inside each part escaped
+// as `%25` and `%5F`, so ids containing underscores round-trip losslessly.
+fn encode_relation_key(relation_name: &str, node_id1: &str, node_id2: &str) -> String {
+    format!(
+        "{}_{}_{}",
+        escape_key_part(relation_name),
+        escape_key_part(node_id1),
+        escape_key_part(node_id2)
+    )
+}
+
+fn parse_relation_key(relation_key: &[u8]) -> Result<(String, String, String)> {
+    let relation_key = std::str::from_utf8(relation_key).context("Relation key is not valid UTF-8")?;
+    let parts: Vec<&str> = relation_key.split('_').collect();
     if parts.len() != 3 {
-        return Err("Invalid relation key".into());
+        bail!("Invalid relation key");
+    }
+    Ok((unescape_key_part(parts[0])?, unescape_key_part(parts[1])?, unescape_key_part(parts[2])?))
+}
+
+// Zero-padded so lexicographic key order matches sequence order
+fn changelog_key(seq: u64) -> String {
+    format!("{:020}", seq)
+}
+
+fn escape_key_part(part: &str) -> String {
+    part.replace('%', "%25").replace('_', "%5F")
+}
+
+fn unescape_key_part(part: &str) -> Result<String> {
+    let mut result = String::with_capacity(part.len());
+    let mut rest = part;
+    while let Some(pos) = rest.find('%') {
+        result.push_str(&rest[..pos]);
+        match rest.get(pos + 1..pos + 3) {
+            Some("25") => result.push('%'),
+            Some("5F") => result.push('_'),
+            _ => bail!("Invalid escape in relation key part {}", part),
+        }
+        rest = &rest[pos + 3..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(
+    path: &Path,
+    schema: arrow::datatypes::Schema,
+    columns: Vec<arrow::array::ArrayRef>,
+) -> Result<()> {
+    let schema = Arc::new(schema);
+    let batch = arrow::record_batch::RecordBatch::try_new(schema.clone(), columns)?;
+    let file = fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer = parquet::arrow::ArrowWriter::try_new(file, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+// Safe in both text and attribute values. Characters XML 1.0 forbids outright
+// (most C0 controls, U+FFFE, U+FFFF) can't even be written as references, so
+// they become U+FFFD.
+fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            '\u{0}'..='\u{1F}' | '\u{FFFE}' | '\u{FFFF}' => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Percent-encodes everything but unreserved ASCII so an id stays one IRI segment
+// and never contains characters N-Triples forbids inside <...>.
+fn escape_iri_segment(segment: &str) -> String {
+    let mut escaped = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        if c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~') || !c.is_ascii() {
+            escaped.push(c);
+        } else {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        }
+    }
+    escaped
+}
+
+fn bfs_distances<'a>(adjacency: &'a HashMap<String, Vec<String>>, start: &'a str) -> HashMap<&'a str, usize> {
+    let mut distances: HashMap<&str, usize> = HashMap::new();
+    let mut queue: VecDeque<&str> = VecDeque::new();
+    distances.insert(start, 0);
+    queue.push_back(start);
+
+    while let Some(node_id) = queue.pop_front() {
+        let distance = distances[node_id];
+        for neighbor in adjacency.get(node_id).into_iter().flatten() {
+            if !distances.contains_key(neighbor.as_str()) {
+                distances.insert(neighbor, distance + 1);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    distances
+}
+
+// Walks two key-ordered LMDB cursors in lockstep, returning the keys only in
+// the first, only in the second, and present in both with different values.
+fn diff_sorted<'a, 'b, V: PartialEq>(
+    mut ours: impl Iterator<Item = heed::Result<(&'a str, V)>>,
+    mut theirs: impl Iterator<Item = heed::Result<(&'b str, V)>>,
+) -> Result<(Vec<String>, Vec<String>, Vec<String>)> {
+    let mut only_ours = Vec::new();
+    let mut only_theirs = Vec::new();
+    let mut changed = Vec::new();
+
+    let mut a = ours.next().transpose()?;
+    let mut b = theirs.next().transpose()?;
+    loop {
+        match (&a, &b) {
+            (None, None) => break,
+            (Some((key, _)), None) => {
+                only_ours.push(key.to_string());
+                a = ours.next().transpose()?;
+            }
+            (None, Some((key, _))) => {
+                only_theirs.push(key.to_string());
+                b = theirs.next().transpose()?;
+            }
+            (Some((key_a, value_a)), Some((key_b, value_b))) => match key_a.cmp(key_b) {
+                Ordering::Less => {
+                    only_ours.push(key_a.to_string());
+                    a = ours.next().transpose()?;
+                }
+                Ordering::Greater => {
+                    only_theirs.push(key_b.to_string());
+                    b = theirs.next().transpose()?;
+                }
+                Ordering::Equal => {
+                    if value_a != value_b {
+                        changed.push(key_a.to_string());
+                    }
+                    a = ours.next().transpose()?;
+                    b = theirs.next().transpose()?;
+                }
+            },
+        }
+    }
+
+    Ok((only_ours, only_theirs, changed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_rhyzome() -> (tempfile::TempDir, Rhyzome) {
+        let dir = tempfile::tempdir().unwrap();
+        let rhyzome = Rhyzome::open(dir.path()).unwrap();
+        (dir, rhyzome)
+    }
+
+    fn weighted(weight: f64) -> Relation {
+        Relation {
+            data: String::new(),
+            timestamp: Utc::now(),
+            expires_at: None,
+            weight,
+        }
+    }
+
+    fn relate(rhyzome: &Rhyzome, relation_name: &str, from: &str, to: &str, weight: f64) {
+        rhyzome.add_relation(relation_name, from, to, weighted(weight)).unwrap();
+    }
+
+    #[test]
+    fn degrees_follow_added_and_removed_edges() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "a", "c", 1.0);
+        relate(&rhyzome, "link", "c", "a", 1.0);
+        // Rewriting an existing edge must not count it twice
+        relate(&rhyzome, "link", "a", "b", 2.0);
+        assert_eq!(rhyzome.degree("a").unwrap(), (2, 1));
+        assert_eq!(rhyzome.degree("b").unwrap(), (0, 1));
+
+        rhyzome.delete_relation("link", "a", "b").unwrap();
+        rhyzome.delete_relation("link", "a", "b").unwrap();
+        assert_eq!(rhyzome.degree("a").unwrap(), (1, 1));
+        assert_eq!(rhyzome.degree("b").unwrap(), (0, 0));
+        assert!(rhyzome.check_integrity().unwrap().degree_mismatches.is_empty());
+    }
+
+    #[test]
+    fn relations_above_weight_returns_only_heavy_edges() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 0.5);
+        relate(&rhyzome, "link", "a", "c", 5.0);
+        relate(&rhyzome, "cite", "c", "d", 9.0);
+
+        let mut heavy = rhyzome.relations_above_weight(1.0).unwrap();
+        heavy.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            heavy,
+            [
+                ("cite".to_string(), "c".to_string(), "d".to_string(), 9.0),
+                ("link".to_string(), "a".to_string(), "c".to_string(), 5.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_bridges_merges_reciprocal_edges() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        // a - b - c with b->a added as well; both edges are still bridges
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "a", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+
+        let mut bridges = rhyzome.find_bridges("link").unwrap();
+        bridges.sort();
+        assert_eq!(bridges, [("a".to_string(), "b".to_string()), ("b".to_string(), "c".to_string())]);
+    }
+
+    #[test]
+    fn find_bridges_skips_edges_on_a_cycle() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+        relate(&rhyzome, "link", "c", "a", 1.0);
+        relate(&rhyzome, "link", "c", "d", 1.0);
+
+        assert_eq!(rhyzome.find_bridges("link").unwrap(), [("c".to_string(), "d".to_string())]);
+    }
+
+    #[test]
+    fn validators_reject_relations_before_anything_is_stored() {
+        let (_dir, mut rhyzome) = temp_rhyzome();
+        rhyzome.register_relation_validator("link", |relation| {
+            let data: serde_json::Value = serde_json::from_str(&relation.data)?;
+            if data.get("label").is_none() {
+                bail!("Missing label");
+            }
+            Ok(())
+        });
+
+        let labelled = Relation { data: r#"{"label":"x"}"#.to_string(), ..weighted(1.0) };
+        rhyzome.add_relation("link", "a", "b", labelled).unwrap();
+        let unlabelled = Relation { data: "{}".to_string(), ..weighted(1.0) };
+        assert!(rhyzome.add_relation("link", "a", "c", unlabelled).is_err());
+
+        assert_eq!(rhyzome.get_relation("link", "a", "c").unwrap(), None);
+        assert_eq!(rhyzome.degree("c").unwrap(), (0, 0));
+        assert_eq!(rhyzome.count_relations().unwrap(), 1);
+        // Other relation names are not validated
+        relate(&rhyzome, "cite", "a", "c", 1.0);
+    }
+
+    #[test]
+    fn transitive_closure_includes_every_downstream_node() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+        relate(&rhyzome, "link", "c", "d", 1.0);
+
+        let closure = rhyzome.transitive_closure("link").unwrap();
+        let expected: HashSet<String> = ["b", "c", "d"].map(String::from).into();
+        assert_eq!(closure["a"], expected);
+        assert_eq!(closure["c"], HashSet::from(["d".to_string()]));
+        assert!(closure["d"].is_empty());
+    }
+
+    #[test]
+    fn iter_nodes_paged_has_no_gaps_or_overlaps() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes((0..25).map(|i| Node::new(format!("n{:02}", i), ""))).unwrap();
+
+        let mut pages = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let page = rhyzome.iter_nodes_paged(after.as_deref(), 10).unwrap();
+            if page.is_empty() {
+                break;
+            }
+            after = page.last().cloned();
+            pages.push(page);
+        }
+        assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [10, 10, 5]);
+        assert_eq!(pages.concat(), rhyzome.iter_nodes().unwrap());
+    }
+
+    #[test]
+    fn diff_reports_divergent_nodes_and_relations() {
+        let (_dir_a, a) = temp_rhyzome();
+        let (_dir_b, b) = temp_rhyzome();
+        let at = Utc::now();
+        for rhyzome in [&a, &b] {
+            rhyzome.add_node(Node { timestamp: at, ..Node::new("shared", "") }).unwrap();
+            rhyzome.add_relation("link", "shared", "x", Relation { timestamp: at, ..weighted(1.0) }).unwrap();
+        }
+        a.add_node(Node::new("only_a", "")).unwrap();
+        b.add_node(Node::new("only_b", "")).unwrap();
+        a.add_node(Node { timestamp: at, ..Node::new("changed", "old") }).unwrap();
+        b.add_node(Node { timestamp: at, ..Node::new("changed", "new") }).unwrap();
+        a.add_relation("link", "x", "y", Relation { timestamp: at, ..weighted(1.0) }).unwrap();
+        a.add_relation("link", "x", "z", Relation { timestamp: at, ..weighted(1.0) }).unwrap();
+        b.add_relation("link", "x", "z", Relation { timestamp: at, ..weighted(2.0) }).unwrap();
+
+        let diff = a.diff(&b).unwrap();
+        assert_eq!(diff.nodes_only_in_self, ["only_a"]);
+        assert_eq!(diff.nodes_only_in_other, ["only_b"]);
+        assert_eq!(diff.nodes_changed, ["changed"]);
+        assert_eq!(diff.relations_only_in_self, ["link_x_y"]);
+        assert!(diff.relations_only_in_other.is_empty());
+        assert_eq!(diff.relations_changed, ["link_x_z"]);
+    }
+
+    fn is_map_full(error: &anyhow::Error) -> bool {
+        error
+            .chain()
+            .any(|cause| matches!(cause.downcast_ref(), Some(heed::Error::Mdb(heed::MdbError::MapFull))))
+    }
+
+    #[test]
+    fn grow_map_lets_writes_continue_after_the_map_fills() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        let data = "x".repeat(64 * 1024);
+        let mut written = 0;
+        let full = loop {
+            match rhyzome.add_node(Node::new(format!("n{}", written), data.clone())) {
+                Ok(()) => written += 1,
+                Err(e) => break e,
+            }
+            assert!(written < 10_000, "map never filled");
+        };
+        assert!(is_map_full(&full), "unexpected error: {:?}", full);
+
+        let config = AutoGrow::default();
+        assert!(grow_map_if_needed(&rhyzome.env, &rhyzome.txn_gate, &config).unwrap());
+        rhyzome.add_node(Node::new("after", data)).unwrap();
+        assert_eq!(rhyzome.count_nodes().unwrap(), written + 1);
+    }
+
+    #[test]
+    fn grow_map_skips_while_a_transaction_is_open() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "data")).unwrap();
+        // Grow at any fill level, so only the open transaction can stop it
+        let config = AutoGrow { min_free_percent: 100.0, ..AutoGrow::default() };
+
+        let ro_txn = rhyzome.read_txn().unwrap();
+        assert!(!grow_map_if_needed(&rhyzome.env, &rhyzome.txn_gate, &config).unwrap());
+        // Reads on the same thread still go through while the first is open
+        assert!(rhyzome.get_node("a").unwrap().is_some());
+        drop(ro_txn);
+
+        assert!(grow_map_if_needed(&rhyzome.env, &rhyzome.txn_gate, &config).unwrap());
+    }
+
+    #[test]
+    fn nested_read_transactions_on_one_thread() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "data")).unwrap();
+
+        let snapshot = rhyzome.snapshot().unwrap();
+        assert!(rhyzome.get_node("a").unwrap().is_some());
+        drop(snapshot);
+
+        let diff = rhyzome.diff(&rhyzome.clone()).unwrap();
+        assert!(diff.nodes_only_in_self.is_empty() && diff.nodes_only_in_other.is_empty());
+        assert!(diff.nodes_changed.is_empty());
+    }
+
+    #[test]
+    fn adjacency_list_matches_the_graph_and_skips_isolated_nodes() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("lonely", "")).unwrap();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "a", "c", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+        relate(&rhyzome, "cite", "c", "a", 1.0);
+
+        let mut adjacency = rhyzome.adjacency_list("link").unwrap();
+        adjacency.values_mut().for_each(|targets| targets.sort());
+        let expected: HashMap<String, Vec<String>> = HashMap::from([
+            ("a".to_string(), vec!["b".to_string(), "c".to_string()]),
+            ("b".to_string(), vec!["c".to_string()]),
+        ]);
+        assert_eq!(adjacency, expected);
+    }
+
+    #[test]
+    fn clearing_outgoing_relations_keeps_the_node_and_incoming_edges() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "data")).unwrap();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "cite", "a", "c", 1.0);
+        relate(&rhyzome, "link", "d", "a", 1.0);
+
+        assert_eq!(rhyzome.clear_node_relations("a", Direction::Outgoing).unwrap(), 2);
+        assert!(rhyzome.get_node("a").unwrap().is_some());
+        assert!(rhyzome.get_related_nodes("a").unwrap().is_empty());
+        assert_eq!(rhyzome.get_incoming_nodes("a").unwrap(), ["d"]);
+        assert_eq!(rhyzome.degree("a").unwrap(), (0, 1));
+    }
+
+    #[test]
+    fn touch_relation_counts_accesses() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+
+        rhyzome.touch_relation("link", "a", "b").unwrap().unwrap();
+        let touched = rhyzome.touch_relation("link", "a", "b").unwrap().unwrap();
+        let data: serde_json::Value = serde_json::from_str(&touched.data).unwrap();
+        assert_eq!(data["access_count"], 2);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn export_parquet_writes_one_row_per_record() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let (dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes([Node::new("a", ""), Node::new("b", ""), Node::new("c", "")]).unwrap();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 2.0);
+
+        let (nodes_path, relations_path) = (dir.path().join("nodes.parquet"), dir.path().join("relations.parquet"));
+        rhyzome.export_parquet(&nodes_path, &relations_path).unwrap();
+
+        let rows = |path: &Path| {
+            let reader = SerializedFileReader::new(fs::File::open(path).unwrap()).unwrap();
+            reader.metadata().file_metadata().num_rows()
+        };
+        assert_eq!(rows(&nodes_path), 3);
+        assert_eq!(rows(&relations_path), 2);
+    }
+
+    #[test]
+    fn scan_range_visits_only_ids_in_range() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["apple", "banana", "cherry", "date", "elder"].map(|id| Node::new(id, ""))).unwrap();
+
+        let mut seen = Vec::new();
+        rhyzome.scan_range(Some("b"), Some("d"), |node_id, _| {
+            seen.push(node_id.to_string());
+            Ok(())
+        }).unwrap();
+        assert_eq!(seen, ["banana", "cherry"]);
+    }
+
+    #[test]
+    fn diameter_of_a_path_is_its_length() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+        relate(&rhyzome, "link", "c", "d", 1.0);
+
+        assert_eq!(rhyzome.diameter().unwrap(), 3);
+        assert_eq!(rhyzome.eccentricity("a").unwrap(), 3);
+        assert_eq!(rhyzome.eccentricity("c").unwrap(), 1);
+    }
+
+    #[test]
+    fn migrate_relation_keys_reencodes_legacy_keys() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes([Node::new("a_1", ""), Node::new("b", "")]).unwrap();
+        let mut txn = rhyzome.write_txn().unwrap();
+        rhyzome.relations_db.put(&mut txn, "link_a_1_b", &weighted(3.0)).unwrap();
+        rhyzome.relations_db.put(&mut txn, "link_x_y", &weighted(1.0)).unwrap();
+        txn.commit().unwrap();
+        let seen = rhyzome.changes_since(0).unwrap().len();
+
+        let report = rhyzome.migrate_relation_keys().unwrap();
+        assert_eq!(report.migrated, 1);
+        assert!(report.ambiguous_keys.is_empty() && report.conflicting_keys.is_empty());
+        assert_eq!(rhyzome.iter_relations().unwrap(), ["link_a%5F1_b", "link_x_y"]);
+        assert_eq!(rhyzome.get_relation("link", "a_1", "b").unwrap().unwrap().weight, 3.0);
+
+        let changes = rhyzome.changes_since(0).unwrap();
+        assert_eq!(changes.len(), seen + 1);
+        assert_eq!(
+            changes.last().unwrap().change,
+            Change::RelationPut { name: "link".into(), from_id: "a_1".into(), to_id: "b".into() }
+        );
+        assert_eq!(rhyzome.migrate_relation_keys().unwrap().migrated, 0);
+    }
+
+    #[test]
+    fn migrate_relation_keys_reports_keys_it_leaves_alone() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["c", "c_d", "d_e", "e", "f_1", "g"].map(|id| Node::new(id, ""))).unwrap();
+        rhyzome.add_relation("link", "f_1", "g", weighted(1.0)).unwrap();
+        let mut txn = rhyzome.write_txn().unwrap();
+        // Splits as c -> d_e and as c_d -> e, both of which exist
+        rhyzome.relations_db.put(&mut txn, "link_c_d_e", &weighted(1.0)).unwrap();
+        // Its escaped form is already stored
+        rhyzome.relations_db.put(&mut txn, "link_f_1_g", &weighted(9.0)).unwrap();
+        txn.commit().unwrap();
+
+        let report = rhyzome.migrate_relation_keys().unwrap();
+        assert_eq!(report.migrated, 0);
+        assert_eq!(report.ambiguous_keys, ["link_c_d_e"]);
+        assert_eq!(report.conflicting_keys, ["link_f_1_g"]);
+        assert_eq!(rhyzome.get_relation("link", "f_1", "g").unwrap().unwrap().weight, 1.0);
+        assert_eq!(rhyzome.iter_relations().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn snapshot_keeps_seeing_the_state_it_was_taken_at() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "before")).unwrap();
+        let snapshot = rhyzome.snapshot().unwrap();
+
+        // Another handle writes, since this thread's snapshot is open
+        let writer = rhyzome.clone();
+        thread::spawn(move || {
+            writer.update_node(Node::new("a", "after")).unwrap();
+            writer.add_node(Node::new("b", "")).unwrap();
+            relate(&writer, "link", "a", "b", 1.0);
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(snapshot.get_node("a").unwrap().unwrap().data, "before");
+        assert_eq!(snapshot.iter_nodes().unwrap(), ["a"]);
+        assert!(snapshot.get_related_nodes("a").unwrap().is_empty());
+        drop(snapshot);
+        assert_eq!(rhyzome.get_node("a").unwrap().unwrap().data, "after");
+    }
+
+    #[test]
+    fn aggregate_into_sums_weights_per_neighbor() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "s1", "x", 1.5);
+        relate(&rhyzome, "link", "s2", "x", 2.0);
+        relate(&rhyzome, "link", "s2", "y", 4.0);
+        relate(&rhyzome, "link", "other", "x", 100.0);
+
+        rhyzome.aggregate_into(&["s1", "s2"], "total", "link").unwrap();
+        assert_eq!(rhyzome.get_relation("link", "total", "x").unwrap().unwrap().weight, 3.5);
+        assert_eq!(rhyzome.get_relation("link", "total", "y").unwrap().unwrap().weight, 4.0);
+        assert_eq!(rhyzome.degree("total").unwrap(), (2, 0));
+    }
+
+    #[test]
+    fn iter_relations_paged_covers_every_key_once() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        for i in 0..25 {
+            relate(&rhyzome, "link", "hub", &format!("n{:02}", i), 1.0);
+        }
+
+        let mut seen = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let page = rhyzome.iter_relations_paged(after.as_deref(), 10).unwrap();
+            assert!(page.len() <= 10);
+            if page.is_empty() {
+                break;
+            }
+            after = page.last().cloned();
+            seen.extend(page);
+        }
+        assert_eq!(seen, rhyzome.iter_relations().unwrap());
+        assert_eq!(seen.len(), 25);
+    }
+
+    #[test]
+    fn k_core_drops_peripheral_nodes() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        let core = ["a", "b", "c", "d"];
+        for (i, id1) in core.iter().enumerate() {
+            for id2 in &core[i + 1..] {
+                relate(&rhyzome, "link", id1, id2, 1.0);
+            }
+        }
+        relate(&rhyzome, "link", "a", "e", 1.0);
+        relate(&rhyzome, "link", "e", "f", 1.0);
+        relate(&rhyzome, "link", "f", "b", 1.0);
+
+        assert_eq!(rhyzome.k_core(3, "link").unwrap(), core);
+        assert_eq!(rhyzome.k_core(2, "link").unwrap(), ["a", "b", "c", "d", "e", "f"]);
+    }
+
+    #[test]
+    fn tags_are_queryable_and_dropped_with_their_node() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes([Node::new("a", ""), Node::new("b", "")]).unwrap();
+        rhyzome.add_tag("a", "red").unwrap();
+        rhyzome.add_tag("b", "red").unwrap();
+        rhyzome.add_tag("b", "blue").unwrap();
+        assert_eq!(rhyzome.nodes_with_tag("red").unwrap(), ["a", "b"]);
+
+        rhyzome.remove_tag("a", "red").unwrap();
+        assert_eq!(rhyzome.nodes_with_tag("red").unwrap(), ["b"]);
+
+        assert!(rhyzome.delete_node("b").unwrap());
+        assert!(rhyzome.nodes_with_tag("red").unwrap().is_empty());
+        assert!(rhyzome.nodes_with_tag("blue").unwrap().is_empty());
+        assert!(rhyzome.check_integrity().unwrap().orphan_tags.is_empty());
+    }
+
+    #[test]
+    fn export_ntriples_writes_one_triple_per_relation() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c d", 1.0);
+        relate(&rhyzome, "cite", "a", "c d", 1.0);
+
+        let mut out = Vec::new();
+        assert_eq!(rhyzome.export_ntriples(&mut out, "http://example.org/").unwrap(), 3);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            let terms: Vec<&str> = line.strip_suffix(" .").unwrap().split(' ').collect();
+            assert_eq!(terms.len(), 3, "bad triple {}", line);
+            for term in terms {
+                let iri = term.strip_prefix("<http://example.org/").and_then(|t| t.strip_suffix('>')).unwrap();
+                assert!(!iri.contains(['<', '>', ' ', '"', '/']), "bad IRI in {}", line);
+            }
+        }
+        assert!(lines.contains(&"<http://example.org/b> <http://example.org/link> <http://example.org/c%20d> ."));
+    }
+
+    #[test]
+    fn import_edge_list_creates_nodes_and_relations() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "kept")).unwrap();
+        let edges = "# comment\na b\n\nb c 2.5\n";
+
+        assert_eq!(rhyzome.import_edge_list(edges.as_bytes(), "link").unwrap(), 2);
+        assert_eq!(rhyzome.iter_nodes().unwrap(), ["a", "b", "c"]);
+        assert_eq!(rhyzome.get_node("a").unwrap().unwrap().data, "kept");
+        assert_eq!(rhyzome.get_relation("link", "b", "c").unwrap().unwrap().weight, 2.5);
+        assert_eq!(rhyzome.get_relation("link", "a", "b").unwrap().unwrap().weight, 1.0);
+
+        assert!(rhyzome.import_edge_list("x y z w\n".as_bytes(), "link").is_err());
+        assert!(rhyzome.get_node("x").unwrap().is_none());
+    }
+
+    #[test]
+    fn concurrent_writers_all_persist() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        thread::scope(|scope| {
+            for i in 0..50 {
+                let rhyzome = &rhyzome;
+                scope.spawn(move || rhyzome.add_node(Node::new(format!("n{}", i), "")).unwrap());
+            }
+        });
+        assert_eq!(rhyzome.count_nodes().unwrap(), 50);
+    }
+
+    #[test]
+    fn recent_nodes_are_newest_first_and_truncated() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes([node_at("old", 30), node_at("newest", 0), node_at("middle", 10), node_at("older", 20)])
+            .unwrap();
+
+        let recent: Vec<String> = rhyzome.recent_nodes(3).unwrap().into_iter().map(|(id, _)| id).collect();
+        assert_eq!(recent, ["newest", "middle", "older"]);
+        assert_eq!(rhyzome.recent_nodes(10).unwrap().len(), 4);
+        assert!(rhyzome.recent_nodes(0).unwrap().is_empty());
+    }
+
+    fn sorted(mut ids: Vec<String>) -> Vec<String> {
+        ids.sort();
+        ids
+    }
+
+    fn node_at(id: &str, seconds_ago: i64) -> Node {
+        Node {
+            timestamp: Utc::now() - chrono::Duration::seconds(seconds_ago),
+            ..Node::new(id, id)
+        }
+    }
+
+    #[test]
+    fn shortest_path_excluding_detours_around_blocked_nodes() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "hub", 1.0);
+        relate(&rhyzome, "link", "hub", "z", 1.0);
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+        relate(&rhyzome, "link", "c", "z", 1.0);
+
+        let none = HashSet::new();
+        assert_eq!(rhyzome.shortest_path_excluding("a", "z", &none).unwrap().unwrap(), ["a", "hub", "z"]);
+        let blocked = HashSet::from(["hub".to_string()]);
+        assert_eq!(rhyzome.shortest_path_excluding("a", "z", &blocked).unwrap().unwrap(), ["a", "b", "c", "z"]);
+        let blocked = HashSet::from(["hub".to_string(), "c".to_string()]);
+        assert_eq!(rhyzome.shortest_path_excluding("a", "z", &blocked).unwrap(), None);
+    }
+
+    #[test]
+    fn delete_node_reports_whether_the_node_existed() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "")).unwrap();
+
+        assert!(rhyzome.delete_node("a").unwrap());
+        assert!(rhyzome.get_node("a").unwrap().is_none());
+        assert!(!rhyzome.delete_node("a").unwrap());
+        assert!(!rhyzome.delete_node("never").unwrap());
+    }
+
+    #[test]
+    fn node_new_stamps_the_current_time() {
+        let node = Node::new("x", "y");
+        assert_eq!((node.id.as_str(), node.data.as_str()), ("x", "y"));
+        assert!((Utc::now() - node.timestamp).num_milliseconds().abs() < 1000);
+    }
+
+    #[test]
+    fn delete_nodes_counts_only_present_ids() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["a", "b", "c"].map(|id| Node::new(id, ""))).unwrap();
+
+        assert_eq!(rhyzome.delete_nodes(&["a", "missing", "c", "gone"]).unwrap(), 2);
+        assert_eq!(rhyzome.iter_nodes().unwrap(), ["b"]);
+    }
+
+    #[test]
+    fn delete_nodes_cascade_removes_relations_and_updates_degrees() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["a", "b", "c", "d"].map(|id| Node::new(id, ""))).unwrap();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+        relate(&rhyzome, "link", "c", "d", 1.0);
+        relate(&rhyzome, "link", "d", "a", 1.0);
+
+        assert_eq!(rhyzome.delete_nodes_cascade(&["a", "missing", "c"]).unwrap(), 2);
+        assert_eq!(rhyzome.iter_nodes().unwrap(), ["b", "d"]);
+        assert!(rhyzome.iter_relations().unwrap().is_empty());
+        assert_eq!(rhyzome.degree("b").unwrap(), (0, 0));
+        assert_eq!(rhyzome.degree("d").unwrap(), (0, 0));
+        assert!(rhyzome.check_integrity().unwrap().is_clean());
+    }
+
+    #[test]
+    fn expired_relations_are_invisible_and_purgeable() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        let expired = Relation {
+            expires_at: Some(Utc::now() - chrono::Duration::seconds(1)),
+            ..weighted(1.0)
+        };
+        rhyzome.add_relation("link", "a", "c", expired).unwrap();
+
+        assert_eq!(rhyzome.get_relation("link", "a", "c").unwrap(), None);
+        assert_eq!(rhyzome.get_related_nodes("a").unwrap(), ["b"]);
+        assert_eq!(rhyzome.bfs("a").unwrap(), ["a", "b"]);
+        assert_eq!(rhyzome.count_relations().unwrap(), 2);
+        // Cached degrees keep counting the expired edge until it's purged
+        assert_eq!(rhyzome.degree("a").unwrap(), (2, 0));
+
+        assert_eq!(rhyzome.purge_expired_relations().unwrap(), 1);
+        assert_eq!(rhyzome.count_relations().unwrap(), 1);
+        assert_eq!(rhyzome.degree("a").unwrap(), (1, 0));
+    }
+
+    #[test]
+    fn touch_relation_ignores_expired_relations() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        let relation = Relation {
+            expires_at: Some(Utc::now() - chrono::Duration::seconds(1)),
+            ..weighted(1.0)
+        };
+        rhyzome.add_relation("link", "a", "b", relation).unwrap();
+
+        assert_eq!(rhyzome.touch_relation("link", "a", "b").unwrap(), None);
+    }
+
+    #[test]
+    fn structural_similarity_rewards_shared_in_neighbors() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        for source in ["s1", "s2", "s3"] {
+            relate(&rhyzome, "link", source, "x", 1.0);
+            relate(&rhyzome, "link", source, "y", 1.0);
+        }
+        relate(&rhyzome, "link", "t", "z", 1.0);
+
+        let overlapping = rhyzome.structural_similarity("x", "y", "link").unwrap();
+        let disjoint = rhyzome.structural_similarity("x", "z", "link").unwrap();
+        assert!(overlapping > 0.25, "overlapping similarity {}", overlapping);
+        assert_eq!(disjoint, 0.0);
+        assert_eq!(rhyzome.structural_similarity("x", "x", "link").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn stats_leave_out_expired_relations() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["a", "b", "c"].map(|id| Node::new(id, ""))).unwrap();
+        relate(&rhyzome, "follows", "a", "b", 1.0);
+        relate(&rhyzome, "likes", "c", "b", 1.0);
+        let expired = Relation {
+            expires_at: Some(Utc::now() - chrono::Duration::seconds(1)),
+            ..weighted(1.0)
+        };
+        rhyzome.add_relation("follows", "a", "c", expired).unwrap();
+        relate(&rhyzome, "follows", "b", "c", 1.0);
+        let expired = Relation {
+            expires_at: Some(Utc::now() - chrono::Duration::seconds(1)),
+            ..weighted(1.0)
+        };
+        rhyzome.add_relation("follows", "b", "a", expired).unwrap();
+
+        let stats = rhyzome.stats().unwrap();
+        let by_name = rhyzome.relation_counts_by_name().unwrap();
+        assert_eq!(stats.node_count, 3);
+        assert_eq!(stats.relation_count, 3);
+        assert_eq!(by_name.values().sum::<usize>(), stats.relation_count);
+        assert_eq!(stats.max_out_degree, 1);
+        assert_eq!(stats.max_in_degree, 2);
+        assert!((stats.mean_degree - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn relations_typed_reports_and_skips_malformed_edges() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Edge {
+            label: String,
+        }
+
+        let (_dir, rhyzome) = temp_rhyzome();
+        let with_data = |data: &str| Relation { data: data.to_string(), ..weighted(1.0) };
+        rhyzome.add_relation("link", "a", "b", with_data(r#"{"label":"ab"}"#)).unwrap();
+        rhyzome.add_relation("link", "a", "c", with_data(r#"{"label":3}"#)).unwrap();
+        rhyzome.add_relation("link", "b", "c", with_data(r#"{"label":"bc"}"#)).unwrap();
+
+        let (edges, errors) = rhyzome.relations_typed_with_errors::<Edge>("link").unwrap();
+        assert_eq!(
+            edges,
+            [
+                ("a".to_string(), "b".to_string(), Edge { label: "ab".to_string() }),
+                ("b".to_string(), "c".to_string(), Edge { label: "bc".to_string() }),
+            ]
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].from_id.as_str(), errors[0].to_id.as_str()), ("a", "c"));
+        assert_eq!(rhyzome.relations_typed::<Edge>("link").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn materialized_neighbors_are_a_point_in_time_copy() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        rhyzome.materialize_neighbors("link").unwrap();
+        assert_eq!(rhyzome.get_materialized_neighbors("a").unwrap().unwrap(), ["b"]);
+
+        relate(&rhyzome, "link", "a", "c", 1.0);
+        assert_eq!(rhyzome.get_materialized_neighbors("a").unwrap().unwrap(), ["b"]);
+
+        rhyzome.materialize_neighbors("link").unwrap();
+        assert_eq!(rhyzome.get_materialized_neighbors("a").unwrap().unwrap(), ["b", "c"]);
+        assert_eq!(rhyzome.get_materialized_neighbors("b").unwrap(), None);
+    }
+
+    #[test]
+    fn swap_node_data_exchanges_data() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes([node_at("a", 60), node_at("b", 60)]).unwrap();
+        let before = Utc::now();
+
+        rhyzome.swap_node_data("a", "b").unwrap();
+        let (a, b) = (rhyzome.get_node("a").unwrap().unwrap(), rhyzome.get_node("b").unwrap().unwrap());
+        assert_eq!((a.data.as_str(), b.data.as_str()), ("b", "a"));
+        assert!(a.timestamp >= before && b.timestamp >= before);
+
+        assert!(rhyzome.swap_node_data("a", "missing").is_err());
+        assert_eq!(rhyzome.get_node("a").unwrap().unwrap().data, "b");
+    }
+
+    #[test]
+    fn batched_changelog_entries_are_visible_before_flushing() {
+        let dir = tempfile::tempdir().unwrap();
+        let batching = ChangelogBatching { max_entries: 100, flush_interval: Duration::from_secs(3600) };
+        let rhyzome = Rhyzome::open(dir.path()).unwrap().with_changelog_batching(batching).unwrap();
+        rhyzome.add_node(Node::new("a", "")).unwrap();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+
+        let changes: Vec<Change> = rhyzome.changes_since(0).unwrap().into_iter().map(|entry| entry.change).collect();
+        assert_eq!(
+            changes,
+            [
+                Change::NodePut { id: "a".to_string() },
+                Change::RelationPut { name: "link".to_string(), from_id: "a".to_string(), to_id: "b".to_string() },
+            ]
+        );
+
+        assert_eq!(rhyzome.flush_changelog().unwrap(), 2);
+        assert_eq!(rhyzome.changes_since(0).unwrap().len(), 2);
+        assert_eq!(rhyzome.changes_since(1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reachable_within_budget_excludes_nodes_past_the_budget() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 2.0);
+        relate(&rhyzome, "link", "c", "d", 5.0);
+        relate(&rhyzome, "link", "a", "c", 10.0);
+
+        assert_eq!(
+            rhyzome.reachable_within_budget("a", "link", 3.0).unwrap(),
+            [("b".to_string(), 1.0), ("c".to_string(), 3.0)]
+        );
+    }
+
+    #[test]
+    fn out_degree_all_counts_edges_per_source() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        for target in ["x", "y", "z"] {
+            relate(&rhyzome, "link", "hub", target, 1.0);
+        }
+        relate(&rhyzome, "link", "x", "y", 1.0);
+        relate(&rhyzome, "link", "y", "z", 1.0);
+        relate(&rhyzome, "cite", "z", "x", 1.0);
+
+        let expected = HashMap::from([("hub".to_string(), 3), ("x".to_string(), 1), ("y".to_string(), 1)]);
+        assert_eq!(rhyzome.out_degree_all("link").unwrap(), expected);
+    }
+
+    #[test]
+    fn check_integrity_flags_a_desynchronized_degree() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "")).unwrap();
+        rhyzome.add_node(Node::new("b", "")).unwrap();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        assert!(rhyzome.check_integrity().unwrap().is_clean());
+
+        let mut txn = rhyzome.write_txn().unwrap();
+        rhyzome.degrees_db.put(&mut txn, "a", &(5, 0)).unwrap();
+        txn.commit().unwrap();
+
+        let report = rhyzome.check_integrity().unwrap();
+        assert_eq!(report.degree_mismatches, ["a"]);
+    }
+
+    #[test]
+    fn check_integrity_pairs_a_legacy_key_with_its_escaped_form() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a_1", "")).unwrap();
+        rhyzome.add_node(Node::new("b", "")).unwrap();
+        relate(&rhyzome, "link", "a_1", "b", 1.0);
+
+        // Written the way keys were before escaping, bypassing the degree counters
+        let mut txn = rhyzome.write_txn().unwrap();
+        rhyzome.relations_db.put(&mut txn, "link_a_1_b", &weighted(1.0)).unwrap();
+        txn.commit().unwrap();
+
+        let report = rhyzome.check_integrity().unwrap();
+        assert_eq!(report.unparseable_relation_keys, ["link_a_1_b"]);
+        assert_eq!(
+            report.duplicate_relations,
+            [("link_a%5F1_b".to_string(), "link_a_1_b".to_string())]
+        );
+        assert!(report.dangling_relations.is_empty());
+        assert!(report.degree_mismatches.is_empty());
+    }
+
+    #[test]
+    fn export_changes_since_only_returns_newer_records() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(node_at("old", 60)).unwrap();
+        let old_relation = Relation { timestamp: Utc::now() - chrono::Duration::seconds(60), ..weighted(1.0) };
+        rhyzome.add_relation("link", "old", "old", old_relation).unwrap();
+        let since = Utc::now() - chrono::Duration::seconds(30);
+        rhyzome.add_node(Node::new("new", "")).unwrap();
+        relate(&rhyzome, "link", "new", "old", 1.0);
+
+        let (nodes, relations) = rhyzome.export_changes_since(since).unwrap();
+        assert_eq!(nodes.iter().map(|node| node.id.as_str()).collect::<Vec<_>>(), ["new"]);
+        assert_eq!(relations.len(), 1);
+        assert_eq!((relations[0].1.as_str(), relations[0].2.as_str()), ("new", "old"));
+    }
+
+    #[test]
+    fn applying_a_delta_converges_two_instances() {
+        let (_dir_a, source) = temp_rhyzome();
+        let (_dir_b, replica) = temp_rhyzome();
+        let since = Utc::now() - chrono::Duration::seconds(1);
+        source.add_nodes([Node::new("a", "1"), Node::new("b", "2")]).unwrap();
+        relate(&source, "link", "a", "b", 2.0);
+
+        let (nodes, relations) = source.export_changes_since(since).unwrap();
+        replica.apply_delta(&nodes, &relations).unwrap();
+        let diff = source.diff(&replica).unwrap();
+        assert!(diff.nodes_only_in_self.is_empty() && diff.nodes_changed.is_empty());
+        assert!(diff.relations_only_in_self.is_empty() && diff.relations_changed.is_empty());
+        assert_eq!(replica.degree("a").unwrap(), (1, 0));
+
+        // Reapplying the same delta changes nothing
+        replica.apply_delta(&nodes, &relations).unwrap();
+        assert!(source.diff(&replica).unwrap().nodes_changed.is_empty());
+    }
+
+    #[test]
+    fn sample_nodes_is_seeded_and_favours_high_degree_nodes() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["hub", "a", "b", "c", "d"].map(|id| Node::new(id, ""))).unwrap();
+        for id in ["a", "b", "c", "d"] {
+            relate(&rhyzome, "link", "hub", id, 1.0);
+        }
+
+        assert_eq!(rhyzome.sample_nodes(20, true, 7).unwrap(), rhyzome.sample_nodes(20, true, 7).unwrap());
+        assert_eq!(rhyzome.sample_nodes(20, false, 7).unwrap(), rhyzome.sample_nodes(20, false, 7).unwrap());
+
+        let sample = rhyzome.sample_nodes(1000, true, 7).unwrap();
+        let hub = sample.iter().filter(|id| *id == "hub").count();
+        let a = sample.iter().filter(|id| *id == "a").count();
+        // The hub has 4 of the 8 degree slots, each leaf 1
+        assert!(hub > 3 * a, "hub sampled {} times, a {} times", hub, a);
+    }
+
+    #[test]
+    fn for_each_relation_stops_when_told() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        for i in 0..20 {
+            relate(&rhyzome, "link", "a", &format!("n{:02}", i), f64::from(i));
+        }
+
+        let mut visited = 0;
+        let mut found = None;
+        rhyzome.for_each_relation(|_, _, to_id, relation| {
+            visited += 1;
+            if relation.weight >= 5.0 {
+                found = Some(to_id.to_string());
+                return Ok(false);
+            }
+            Ok(true)
+        }).unwrap();
+        assert_eq!(found.as_deref(), Some("n05"));
+        assert_eq!(visited, 6);
+    }
+
+    #[test]
+    fn iter_nodes_lists_every_inserted_id() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        for id in ["c", "a", "b"] {
+            rhyzome.add_node(Node::new(id, "")).unwrap();
+        }
+        assert_eq!(rhyzome.iter_nodes().unwrap(), ["a", "b", "c"]);
+        assert_eq!(rhyzome.query_nodes(|key| key == b"b").unwrap(), ["b"]);
+    }
+
+    #[test]
+    fn bfs_visits_in_breadth_first_order() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "A", "B", 1.0);
+        relate(&rhyzome, "link", "A", "C", 1.0);
+        relate(&rhyzome, "link", "B", "D", 1.0);
+
+        assert_eq!(rhyzome.bfs("A").unwrap(), ["A", "B", "C", "D"]);
+    }
+
+    #[test]
+    fn clones_on_separate_threads_write_to_one_store() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        let handles: Vec<_> = ["left", "right"]
+            .into_iter()
+            .map(|side| {
+                let rhyzome = rhyzome.clone();
+                thread::spawn(move || {
+                    for i in 0..10 {
+                        rhyzome.add_node(Node::new(format!("{}{}", side, i), "")).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(rhyzome.nodes_with_prefix("left").unwrap().len(), 10);
+        assert_eq!(rhyzome.nodes_with_prefix("right").unwrap().len(), 10);
+    }
+
+    #[test]
+    fn relate_upsert_creates_missing_endpoints() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "original")).unwrap();
+        rhyzome.relate_upsert("link", "a", "ignored", "b", "fresh", weighted(1.0)).unwrap();
+        rhyzome.relate_upsert("link", "c", "new", "d", "new", weighted(1.0)).unwrap();
+
+        assert_eq!(rhyzome.get_node("a").unwrap().unwrap().data, "original");
+        assert_eq!(rhyzome.get_node("b").unwrap().unwrap().data, "fresh");
+        assert!(rhyzome.get_node("c").unwrap().is_some() && rhyzome.get_node("d").unwrap().is_some());
+        assert!(rhyzome.get_relation("link", "c", "d").unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn async_rhyzome_caps_concurrent_operations() {
+        use std::sync::atomic::AtomicUsize;
+
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_node(Node::new("a", "")).unwrap();
+        let rhyzome = AsyncRhyzome::new(rhyzome, 2).unwrap();
+        assert_eq!(rhyzome.threads(), 2);
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let (rhyzome, in_flight, peak) = (rhyzome.clone(), in_flight.clone(), peak.clone());
+                tokio::spawn(async move {
+                    rhyzome.run(move |rhyzome| {
+                        let now = in_flight.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+                        peak.fetch_max(now, AtomicOrdering::SeqCst);
+                        thread::sleep(Duration::from_millis(10));
+                        let node = rhyzome.get_node("a");
+                        in_flight.fetch_sub(1, AtomicOrdering::SeqCst);
+                        node
+                    })
+                    .await
+                })
+            })
+            .collect();
+
+        for task in tasks {
+            assert!(task.await.unwrap().unwrap().is_some());
+        }
+        assert_eq!(peak.load(AtomicOrdering::SeqCst), 2);
+    }
+
+    #[test]
+    fn page_nodes_returns_the_page_and_the_total() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Post {
+            n: u32,
+        }
+
+        let (_dir, rhyzome) = temp_rhyzome();
+        for n in 0..5 {
+            rhyzome.add_node(Node::new(format!("p{}", n), format!(r#"{{"n":{}}}"#, n))).unwrap();
+        }
+        rhyzome.add_node(Node::new("p2x", "not json")).unwrap();
+
+        let (page, total) = rhyzome.page_nodes::<Post>(Some("p1"), 2).unwrap();
+        assert_eq!(page, [("p2".to_string(), Post { n: 2 }), ("p3".to_string(), Post { n: 3 })]);
+        assert_eq!(total, rhyzome.count_nodes().unwrap());
+        assert_eq!(total, 6);
+    }
+
+    #[test]
+    fn relations_between_ids_with_underscores_round_trip() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "links_to", "a_b", "c_d_e", 1.0);
+        relate(&rhyzome, "links_to", "100%_x", "c_d_e", 1.0);
+
+        assert!(rhyzome.get_relation("links_to", "a_b", "c_d_e").unwrap().is_some());
+        assert_eq!(rhyzome.get_related_nodes("a_b").unwrap(), ["c_d_e"]);
+        assert_eq!(sorted(rhyzome.get_incoming_nodes("c_d_e").unwrap()), ["100%_x", "a_b"]);
+        assert_eq!(rhyzome.relation_names().unwrap(), ["links_to"]);
+    }
+
+    #[test]
+    fn corrupt_nodes_fail_with_their_id_and_stay_readable_raw() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        let mut txn = rhyzome.write_txn().unwrap();
+        rhyzome.node_db.remap_data_type::<Bytes>().put(&mut txn, "bad", b"\xff garbage").unwrap();
+        txn.commit().unwrap();
+
+        let is_corrupt = |error: anyhow::Error| {
+            matches!(error.downcast_ref(), Some(RhyzomeError::Corrupt { id, .. }) if id == "bad")
+        };
+        assert!(is_corrupt(rhyzome.get_node("bad").unwrap_err()));
+        assert!(is_corrupt(rhyzome.snapshot().unwrap().get_node("bad").unwrap_err()));
+        assert_eq!(rhyzome.get_node_raw("bad").unwrap().unwrap(), b"\xff garbage");
+    }
+
+    #[test]
+    fn related_with_weights_returns_each_edge_weight() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 2.0);
+        relate(&rhyzome, "cite", "a", "c", 5.0);
+
+        let mut related = rhyzome.related_with_weights("a").unwrap();
+        related.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(related, [("b".to_string(), 2.0), ("c".to_string(), 5.0)]);
+        assert_eq!(sorted(rhyzome.get_related_nodes("a").unwrap()), ["b", "c"]);
+    }
+
+    #[test]
+    fn shortest_path_follows_a_chain() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 2.0);
+
+        let (path, cost) = rhyzome.shortest_path("a", "c", "link").unwrap().unwrap();
+        assert_eq!(path, ["a", "b", "c"]);
+        assert_eq!(cost, 3.0);
+    }
+
+    #[test]
+    fn shortest_path_picks_the_cheaper_of_two_paths() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "d", 1.0);
+        relate(&rhyzome, "link", "a", "c", 0.5);
+        relate(&rhyzome, "link", "c", "d", 5.0);
+
+        let (path, cost) = rhyzome.shortest_path("a", "d", "link").unwrap().unwrap();
+        assert_eq!(path, ["a", "b", "d"]);
+        assert_eq!(cost, 2.0);
+        let zero = |_: &str| 0.0;
+        assert_eq!(rhyzome.astar("a", "d", "link", zero).unwrap(), Some((path, cost)));
+    }
+
+    #[test]
+    fn shortest_path_is_none_when_disconnected() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "c", "d", 1.0);
+
+        assert_eq!(rhyzome.shortest_path("a", "d", "link").unwrap(), None);
+        assert_eq!(rhyzome.astar("a", "d", "link", |_| 0.0).unwrap(), None);
+    }
+
+    #[test]
+    fn weighted_searches_reject_negative_and_nan_weights() {
+        for weight in [-1.0, f64::NAN] {
+            let (_dir, rhyzome) = temp_rhyzome();
+            relate(&rhyzome, "link", "a", "b", 1.0);
+            relate(&rhyzome, "link", "b", "a", weight);
+
+            assert!(rhyzome.shortest_path("a", "b", "link").is_err());
+            assert!(rhyzome.reachable_within_budget("a", "link", 10.0).is_err());
+            assert!(rhyzome.astar("a", "b", "link", |_| 0.0).is_err());
+        }
+    }
+
+    #[test]
+    fn add_nodes_commits_a_large_batch_at_once() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        let written = rhyzome.add_nodes((0..10_000).map(|i| Node::new(format!("n{:05}", i), ""))).unwrap();
+
+        assert_eq!(written, 10_000);
+        assert_eq!(rhyzome.count_nodes().unwrap(), 10_000);
+        assert!(rhyzome.get_node("n09999").unwrap().is_some());
+        assert_eq!(rhyzome.changes_since(0).unwrap().len(), 10_000);
+    }
+
+    #[test]
+    fn nodes_with_prefix_matches_only_the_prefix() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["user:1", "post:1", "user:2", "users"].map(|id| Node::new(id, ""))).unwrap();
+
+        assert_eq!(rhyzome.nodes_with_prefix("user:").unwrap(), ["user:1", "user:2"]);
+        assert!(rhyzome.nodes_with_prefix("tag:").unwrap().is_empty());
+    }
+
+    #[test]
+    fn count_nodes_tracks_inserts_and_deletes() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        assert_eq!(rhyzome.count_nodes().unwrap(), 0);
+        rhyzome.add_nodes(["a", "b", "c"].map(|id| Node::new(id, ""))).unwrap();
+        rhyzome.update_node(Node::new("a", "again")).unwrap();
+        assert_eq!(rhyzome.count_nodes().unwrap(), 3);
+
+        rhyzome.delete_node("b").unwrap();
+        rhyzome.delete_node("missing").unwrap();
+        assert_eq!(rhyzome.count_nodes().unwrap(), 2);
+    }
+
+    #[test]
+    fn for_each_node_stops_when_told() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes((0..100).map(|i| Node::new(format!("n{:03}", i), ""))).unwrap();
+
+        let mut calls = 0;
+        rhyzome.for_each_node(|_, _| {
+            calls += 1;
+            Ok(calls < 5)
+        }).unwrap();
+        assert_eq!(calls, 5);
+    }
+
+    #[test]
+    fn page_node_entries_pages_through_every_node() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes((0..25).map(|i| Node::new(format!("n{:02}", i), format!("data {}", i)))).unwrap();
+
+        let mut pages = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let page = rhyzome.page_node_entries(after.as_deref(), 10).unwrap();
+            after = page.last().map(|(id, _)| id.clone());
+            let last = page.len() < 10;
+            pages.push(page);
+            if last {
+                break;
+            }
+        }
+
+        assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [10, 10, 5]);
+        let entries: Vec<(String, Node)> = pages.into_iter().flatten().collect();
+        assert!(entries.iter().all(|(id, node)| *id == node.id));
+        assert_eq!(entries[12].1.data, "data 12");
+        let ids: Vec<String> = entries.into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, rhyzome.iter_nodes().unwrap());
+    }
+
+    #[test]
+    fn bidirectional_relations_link_both_ways() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_bidirectional_relation("friend", "A", "B", weighted(1.0)).unwrap();
+
+        assert_eq!(rhyzome.get_related_nodes("A").unwrap(), ["B"]);
+        assert_eq!(rhyzome.get_related_nodes("B").unwrap(), ["A"]);
+
+        rhyzome.delete_bidirectional_relation("friend", "B", "A").unwrap();
+        assert_eq!(rhyzome.count_relations().unwrap(), 0);
+    }
+
+    #[test]
+    fn incoming_nodes_list_every_source() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "A", "C", 1.0);
+        relate(&rhyzome, "link", "B", "C", 1.0);
+        relate(&rhyzome, "link", "C", "D", 1.0);
+
+        assert_eq!(rhyzome.get_incoming_nodes("C").unwrap(), ["A", "B"]);
+    }
+
+    #[test]
+    fn cascade_delete_removes_the_nodes_relations() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["A", "B", "C"].map(|id| Node::new(id, ""))).unwrap();
+        relate(&rhyzome, "link", "A", "B", 1.0);
+        relate(&rhyzome, "link", "C", "A", 1.0);
+        relate(&rhyzome, "link", "B", "C", 1.0);
+
+        assert_eq!(rhyzome.delete_node_cascade("A").unwrap(), 2);
+        assert!(rhyzome.get_node("A").unwrap().is_none());
+        assert_eq!(rhyzome.iter_relations().unwrap(), ["link_B_C"]);
+        assert_eq!(rhyzome.degree("B").unwrap(), (1, 0));
+        assert!(rhyzome.check_integrity().unwrap().is_clean());
+    }
+
+    #[test]
+    fn has_cycle_detects_loops() {
+        let (_dir, chain) = temp_rhyzome();
+        relate(&chain, "link", "A", "B", 1.0);
+        relate(&chain, "link", "B", "C", 1.0);
+        relate(&chain, "link", "A", "C", 1.0);
+        assert!(!chain.has_cycle("link").unwrap());
+
+        let (_dir, self_loop) = temp_rhyzome();
+        relate(&self_loop, "link", "A", "A", 1.0);
+        assert!(self_loop.has_cycle("link").unwrap());
+
+        let (_dir, triangle) = temp_rhyzome();
+        relate(&triangle, "link", "A", "B", 1.0);
+        relate(&triangle, "link", "B", "C", 1.0);
+        relate(&triangle, "link", "C", "A", 1.0);
+        assert!(triangle.has_cycle("link").unwrap());
+        assert!(!triangle.has_cycle("other").unwrap());
+    }
+
+    #[test]
+    fn topological_order_puts_sources_before_sinks() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "A", "B", 1.0);
+        relate(&rhyzome, "link", "A", "C", 1.0);
+        relate(&rhyzome, "link", "B", "D", 1.0);
+        relate(&rhyzome, "link", "C", "D", 1.0);
+
+        assert_eq!(rhyzome.topological_order("link").unwrap(), ["A", "B", "C", "D"]);
+
+        relate(&rhyzome, "link", "D", "A", 1.0);
+        assert!(rhyzome.topological_order("link").is_err());
+    }
+
+    #[test]
+    fn connected_components_ignore_edge_direction() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        for triangle in [["a", "b", "c"], ["x", "y", "z"]] {
+            relate(&rhyzome, "link", triangle[0], triangle[1], 1.0);
+            relate(&rhyzome, "link", triangle[2], triangle[1], 1.0);
+            relate(&rhyzome, "link", triangle[0], triangle[2], 1.0);
+        }
+
+        assert_eq!(rhyzome.connected_components("link").unwrap(), [["a", "b", "c"], ["x", "y", "z"]]);
+    }
+
+    #[test]
+    fn neighbors_within_stops_at_the_hop_limit() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "A", "B", 1.0);
+        relate(&rhyzome, "link", "B", "C", 1.0);
+        relate(&rhyzome, "link", "C", "D", 1.0);
+
+        assert_eq!(rhyzome.neighbors_within("A", "link", 2).unwrap(), ["B", "C"]);
+        assert!(rhyzome.neighbors_within("A", "link", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn get_related_checked_tells_missing_nodes_from_empty_ones() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes(["a", "b", "c", "lonely"].map(|id| Node::new(id, ""))).unwrap();
+        relate(&rhyzome, "follows", "a", "b", 1.0);
+        relate(&rhyzome, "follows", "a", "c", 1.0);
+        relate(&rhyzome, "likes", "lonely", "a", 1.0);
+
+        assert_eq!(rhyzome.get_related_checked("missing", "follows").unwrap(), None);
+        assert_eq!(sorted(rhyzome.get_related_checked("a", "follows").unwrap().unwrap()), ["b", "c"]);
+        assert_eq!(rhyzome.get_related_checked("lonely", "follows").unwrap(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn json_export_imports_into_an_equal_store() {
+        let (_dir, source) = temp_rhyzome();
+        source.add_nodes([Node::new("a", "first"), Node::new("b_1", "second")]).unwrap();
+        relate(&source, "link", "a", "b_1", 2.5);
+        relate(&source, "cite", "b_1", "a", 1.0);
+
+        let mut dump = Vec::new();
+        source.export_json(&mut dump).unwrap();
+        let (_dir, copy) = temp_rhyzome();
+        copy.import_json(dump.as_slice()).unwrap();
+
+        let diff = source.diff(&copy).unwrap();
+        assert!(diff.nodes_only_in_self.is_empty() && diff.nodes_only_in_other.is_empty());
+        assert!(diff.nodes_changed.is_empty() && diff.relations_changed.is_empty());
+        assert!(diff.relations_only_in_self.is_empty() && diff.relations_only_in_other.is_empty());
+        assert_eq!(copy.degree("a").unwrap(), (1, 1));
+
+        assert!(copy.import_json(dump.as_slice()).is_err());
+    }
+
+    #[test]
+    fn export_graphml_is_well_formed() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        rhyzome.add_nodes([Node::new("a", "<b>&\u{1}"), Node::new("b", "")]).unwrap();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        // "c" only exists as an edge endpoint
+        relate(&rhyzome, "link", "b", "c", 2.0);
+
+        let mut out = Vec::new();
+        rhyzome.export_graphml(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let document = roxmltree::Document::parse(&out).unwrap();
+
+        let count = |tag: &str| document.descendants().filter(|node| node.has_tag_name(tag)).count();
+        assert_eq!(count("node"), 3);
+        assert_eq!(count("edge"), 2);
+        let data = document.descendants().find(|node| node.attribute("key") == Some("data")).unwrap();
+        assert_eq!(data.text(), Some("<b>&\u{FFFD}"));
+    }
+
+    #[test]
+    fn escape_xml_replaces_characters_xml_forbids() {
+        assert_eq!(escape_xml("a<b & \"c\""), "a&lt;b &amp; &quot;c&quot;");
+        assert_eq!(escape_xml("tab\tnl\n"), "tab\tnl\n");
+        assert_eq!(escape_xml("bell\u{7}end\u{FFFF}"), "bell\u{FFFD}end\u{FFFD}");
+    }
+
+    #[test]
+    fn pagerank_of_a_cycle_is_uniform() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+        relate(&rhyzome, "link", "c", "a", 1.0);
+
+        let ranks = rhyzome.pagerank("link", 0.85, 50).unwrap();
+        assert_eq!(ranks.len(), 3);
+        for (_, rank) in &ranks {
+            assert!((rank - 1.0 / 3.0).abs() < 1e-9);
+        }
+
+        // A sink fed by both others ranks highest; scores still sum to 1
+        let (_dir, star) = temp_rhyzome();
+        relate(&star, "link", "a", "hub", 1.0);
+        relate(&star, "link", "b", "hub", 1.0);
+        let ranks = star.pagerank("link", 0.85, 50).unwrap();
+        assert_eq!(ranks[0].0, "hub");
+        assert!((ranks.iter().map(|(_, rank)| rank).sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn pagerank_matches_the_classic_four_node_example() {
+        // A -> B, A -> C, B -> C, C -> A, D -> C with damping 0.85, solved exactly
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "A", "B", 1.0);
+        relate(&rhyzome, "link", "A", "C", 1.0);
+        relate(&rhyzome, "link", "B", "C", 1.0);
+        relate(&rhyzome, "link", "C", "A", 1.0);
+        relate(&rhyzome, "link", "D", "C", 1.0);
+
+        let ranks = rhyzome.pagerank("link", 0.85, 100).unwrap();
+        let expected = [("C", 0.394149), ("A", 0.372527), ("B", 0.195824), ("D", 0.0375)];
+        assert_eq!(ranks.len(), expected.len());
+        for ((id, rank), (expected_id, expected_rank)) in ranks.iter().zip(expected) {
+            assert_eq!(id, expected_id);
+            assert!((rank - expected_rank).abs() < 1e-5, "{} ranked {}", id, rank);
+        }
+    }
+
+    #[test]
+    fn is_reachable_follows_the_closure_of_a_chain() {
+        let (_dir, rhyzome) = temp_rhyzome();
+        relate(&rhyzome, "link", "a", "b", 1.0);
+        relate(&rhyzome, "link", "b", "c", 1.0);
+
+        let closure = rhyzome.transitive_closure("link").unwrap();
+        assert!(Rhyzome::is_reachable(&closure, "a", "b"));
+        assert!(Rhyzome::is_reachable(&closure, "a", "c"));
+        assert!(Rhyzome::is_reachable(&closure, "b", "c"));
+        assert!(closure["c"].is_empty());
+        assert!(!Rhyzome::is_reachable(&closure, "c", "a"));
+        assert!(!Rhyzome::is_reachable(&closure, "a", "a"));
     }
-    Ok((parts[0].to_string(), parts[1].to_string(), parts[2].to_string()))
 }
@@ -1,13 +1,37 @@
-use sqlx::{Error, PgConnection, PgPool, Postgres, Row};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Error, Executor, PgPool, Postgres, QueryBuilder, Row};
 use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::io::{BufRead, BufReader, Read};
+use std::time::Duration;
+
+const MAX_TX_ATTEMPTS: u32 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(10);
+// Postgres caps a single statement at 65535 bind parameters
+const MAX_BIND_PARAMS: usize = 65535;
+const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+const DEFAULT_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
 
 pub struct Rhyzome {
     pool: PgPool,
 }
 
 impl Rhyzome {
+    // Defaults: at most 10 connections, 30s to acquire one, and idle
+    // connections closed after 10 minutes.
     pub async fn new(database_url: &str) -> Result<Rhyzome, Error> {
-        let pool = PgPool::connect(database_url).await?;
+        let opts = PgPoolOptions::new()
+            .max_connections(DEFAULT_MAX_CONNECTIONS)
+            .acquire_timeout(DEFAULT_ACQUIRE_TIMEOUT)
+            .idle_timeout(DEFAULT_IDLE_TIMEOUT);
+        Rhyzome::with_options(database_url, opts).await
+    }
+
+    pub async fn with_options(database_url: &str, opts: PgPoolOptions) -> Result<Rhyzome, Error> {
+        let pool = opts.connect(database_url).await?;
 
         // Initialize types and tables if they don't exist
         pool.execute(
@@ -18,6 +42,9 @@ impl Rhyzome {
         )
         .await?;
 
+        pool.execute("ALTER TABLE nodes ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now()")
+            .await?;
+
         pool.execute(
             "CREATE TABLE IF NOT EXISTS relations (
                 name TEXT,
@@ -27,16 +54,38 @@ impl Rhyzome {
         )
         .await?;
 
-        Ok(Rhyzome { pool })
-    }
+        pool.execute("ALTER TABLE relations ADD COLUMN IF NOT EXISTS weight DOUBLE PRECISION NOT NULL DEFAULT 1.0")
+            .await?;
 
-    pub async fn set(&self, id: &str, value: &str) -> Result<(), Error> {
-        sqlx::query("INSERT INTO nodes (id, value) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET value = $2")
-            .bind(id)
-            .bind(value)
-            .execute(&self.pool)
+        pool.execute("ALTER TABLE relations ADD COLUMN IF NOT EXISTS id BIGSERIAL PRIMARY KEY")
             .await?;
-        Ok(())
+
+        let rhyzome = Rhyzome { pool };
+
+        // Older tables may already hold duplicates, which would make the index fail
+        rhyzome.dedup_relations().await?;
+        rhyzome.pool.execute("CREATE UNIQUE INDEX IF NOT EXISTS relations_name_from_to ON relations (name, from_id, to_id)")
+            .await?;
+
+        Ok(rhyzome)
+    }
+
+    pub async fn set(&self, id: &str, value: &str) -> Result<SetOutcome, Error> {
+        // xmax is only zero on a row version this statement freshly inserted
+        let row = sqlx::query(
+            "INSERT INTO nodes (id, value) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET value = $2
+             RETURNING (xmax = 0) AS inserted",
+        )
+        .bind(id)
+        .bind(value)
+        .fetch_one(&self.pool)
+        .await?;
+
+        if row.get::<bool, _>("inserted") {
+            Ok(SetOutcome::Insert)
+        } else {
+            Ok(SetOutcome::Update)
+        }
     }
 
     pub async fn get(&self, id: &str) -> Result<Option<String>, Error> {
@@ -48,6 +97,21 @@ impl Rhyzome {
         Ok(row.map(|r| r.get("value")))
     }
 
+    pub async fn set_json<T: Serialize>(&self, id: &str, value: &T) -> Result<SetOutcome, Error> {
+        let value = serde_json::to_string(value)
+            .map_err(|e| Error::Encode(format!("Failed to serialize node {}: {}", id, e).into()))?;
+        self.set(id, &value).await
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(&self, id: &str) -> Result<Option<T>, Error> {
+        match self.get(id).await? {
+            Some(value) => serde_json::from_str(&value)
+                .map(Some)
+                .map_err(|e| Error::Decode(format!("Node {} does not hold the expected JSON: {}", id, e).into())),
+            None => Ok(None),
+        }
+    }
+
     pub async fn delete(&self, id: &str) -> Result<(), Error> {
         sqlx::query("DELETE FROM nodes WHERE id = $1")
             .bind(id)
@@ -56,14 +120,81 @@ impl Rhyzome {
         Ok(())
     }
 
-    pub async fn relate(&self, from_id: &str, relation_name: &str, to_id: &str) -> Result<(), Error> {
-        sqlx::query("INSERT INTO relations (name, from_id, to_id) VALUES ($1, $2, $3)")
+    // Returns the edge's id, whether it was just created or already existed.
+    pub async fn relate(&self, from_id: &str, relation_name: &str, to_id: &str) -> Result<i64, Error> {
+        // The no-op update makes RETURNING yield the existing row on conflict
+        let row = sqlx::query(
+            "INSERT INTO relations (name, from_id, to_id) VALUES ($1, $2, $3)
+             ON CONFLICT (name, from_id, to_id) DO UPDATE SET name = EXCLUDED.name
+             RETURNING id",
+        )
+        .bind(relation_name)
+        .bind(from_id)
+        .bind(to_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.get("id"))
+    }
+
+    // Returns false when the edge already existed.
+    pub async fn relate_idempotent(&self, from_id: &str, relation_name: &str, to_id: &str) -> Result<bool, Error> {
+        let result = sqlx::query("INSERT INTO relations (name, from_id, to_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING")
             .bind(relation_name)
             .bind(from_id)
             .bind(to_id)
             .execute(&self.pool)
             .await?;
-        Ok(())
+        Ok(result.rows_affected() == 1)
+    }
+
+    // Edges are (from_id, relation_name, to_id), matching relate's argument order.
+    // Already-present edges are skipped and not counted.
+    pub async fn relate_many(&self, edges: &[(String, String, String)]) -> Result<u64, Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut rows_affected = 0;
+
+        for chunk in edges.chunks(MAX_BIND_PARAMS / 3) {
+            let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("INSERT INTO relations (name, from_id, to_id) ");
+            builder.push_values(chunk, |mut row, (from_id, relation_name, to_id)| {
+                row.push_bind(relation_name).push_bind(from_id).push_bind(to_id);
+            });
+            builder.push(" ON CONFLICT DO NOTHING");
+            rows_affected += builder.build().execute(&mut *tx).await?.rows_affected();
+        }
+
+        tx.commit().await?;
+        Ok(rows_affected)
+    }
+
+    // Rows are `from_id,to_id`; blank lines are ignored. A first row naming
+    // the columns (`from_id,to_id` or `from,to`) is taken as a header and skipped.
+    pub async fn import_edges_csv<R: Read>(&self, reader: R, relation_name: &str) -> Result<u64, Error> {
+        let mut edges: Vec<(String, String, String)> = Vec::new();
+        let mut first_row = true;
+
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            match parse_edge_row(line) {
+                Some((from_id, to_id)) if first_row && is_edge_header(from_id, to_id) => {}
+                Some((from_id, to_id)) => {
+                    edges.push((from_id.to_string(), relation_name.to_string(), to_id.to_string()));
+                }
+                None => {
+                    return Err(Error::InvalidArgument(format!(
+                        "Expected `from_id,to_id` on line {} of edge CSV",
+                        index + 1
+                    )))
+                }
+            }
+            first_row = false;
+        }
+
+        self.relate_many(&edges).await
     }
 
     pub async fn get_related(&self, id: &str, relation_name: &str) -> Result<Vec<String>, Error> {
@@ -77,7 +208,27 @@ impl Rhyzome {
         Ok(related_ids)
     }
 
-    pub async fn dfs(&self, start_id: &str) -> Result<Vec<String>, Error> {
+    // None when the node itself doesn't exist, as opposed to having no edges.
+    pub async fn get_related_checked(&self, id: &str, relation_name: &str) -> Result<Option<Vec<String>>, Error> {
+        // The left join yields one row with a NULL to_id for an existing node without edges
+        let rows = sqlx::query(
+            "SELECT r.to_id FROM nodes n
+             LEFT JOIN relations r ON r.from_id = n.id AND r.name = $2
+             WHERE n.id = $1",
+        )
+        .bind(id)
+        .bind(relation_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let related_ids: Vec<String> = rows.iter().filter_map(|r| r.get::<Option<String>, _>("to_id")).collect();
+        Ok(Some(related_ids))
+    }
+
+    pub async fn dfs(&self, start_id: &str, relation_name: &str) -> Result<Vec<String>, Error> {
         let mut visited = HashSet::new();
         let mut stack = Vec::new();
         let mut result = Vec::new();
@@ -88,7 +239,7 @@ impl Rhyzome {
         while let Some(id) = stack.pop() {
             result.push(id.clone());
 
-            let related_ids = self.get_related(&id, "related").await?;
+            let related_ids = self.get_related(&id, relation_name).await?;
             for related_id in related_ids {
                 if !visited.contains(&related_id) {
                     visited.insert(related_id.clone());
@@ -100,7 +251,7 @@ impl Rhyzome {
         Ok(result)
     }
 
-    pub async fn bfs(&self, start_id: &str) -> Result<Vec<String>, Error> {
+    pub async fn bfs(&self, start_id: &str, relation_name: &str) -> Result<Vec<String>, Error> {
         let mut visited = HashSet::new();
         let mut queue = VecDeque::new();
         let mut result = Vec::new();
@@ -111,7 +262,7 @@ impl Rhyzome {
         while let Some(id) = queue.pop_front() {
             result.push(id.clone());
 
-            let related_ids = self.get_related(&id, "related").await?;
+            let related_ids = self.get_related(&id, relation_name).await?;
             for related_id in related_ids {
                 if !visited.contains(&related_id) {
                     visited.insert(related_id.clone());
@@ -132,6 +283,61 @@ impl Rhyzome {
         Ok(ids)
     }
 
+    pub async fn recent_nodes(&self, limit: usize) -> Result<Vec<(String, String)>, Error> {
+        let rows = sqlx::query("SELECT id, value FROM nodes ORDER BY created_at DESC, id LIMIT $1")
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .fetch_all(&self.pool)
+            .await?;
+
+        let nodes: Vec<(String, String)> = rows.iter().map(|r| (r.get("id"), r.get("value"))).collect();
+        Ok(nodes)
+    }
+
+    // Keeps the first physical row of each (name, from_id, to_id), folding the
+    // duplicates' weights into it. Both halves run against one snapshot.
+    pub async fn dedup_relations(&self) -> Result<u64, Error> {
+        let result = sqlx::query(
+            "WITH ranked AS (
+                SELECT ctid,
+                    row_number() OVER w AS rn,
+                    count(*) OVER w AS copies,
+                    sum(weight) OVER w AS total
+                FROM relations
+                WINDOW w AS (PARTITION BY name, from_id, to_id ORDER BY ctid
+                             ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING)
+            ), merged AS (
+                UPDATE relations r SET weight = ranked.total
+                FROM ranked
+                WHERE r.ctid = ranked.ctid AND ranked.rn = 1 AND ranked.copies > 1
+            )
+            DELETE FROM relations r
+            USING ranked
+            WHERE r.ctid = ranked.ctid AND ranked.rn > 1",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    // Runs `f` (which should open and commit its own transaction on the given
+    // pool) and retries with exponential backoff on serialization failures.
+    pub async fn with_retry<F, Fut, T>(&self, mut f: F) -> Result<T, Error>
+    where
+        F: FnMut(PgPool) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match f(self.pool.clone()).await {
+                Err(e) if is_serialization_failure(&e) && attempt + 1 < MAX_TX_ATTEMPTS => {
+                    tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
     pub async fn query(&self, query: &str) -> Result<Vec<String>, Error> {
         let rows = sqlx::query(query)
             .fetch_all(&self.pool)
@@ -141,3 +347,307 @@ impl Rhyzome {
         Ok(results)
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOutcome {
+    Insert,
+    Update,
+}
+
+// 40001 serialization_failure, 40P01 deadlock_detected
+fn is_serialization_failure(error: &Error) -> bool {
+    match error {
+        Error::Database(db_error) => matches!(db_error.code().as_deref(), Some("40001") | Some("40P01")),
+        _ => false,
+    }
+}
+
+fn parse_edge_row(line: &str) -> Option<(&str, &str)> {
+    let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+    match fields.as_slice() {
+        [from_id, to_id] if !from_id.is_empty() && !to_id.is_empty() => Some((from_id, to_id)),
+        _ => None,
+    }
+}
+
+fn is_edge_header(from_id: &str, to_id: &str) -> bool {
+    (from_id.eq_ignore_ascii_case("from_id") && to_id.eq_ignore_ascii_case("to_id"))
+        || (from_id.eq_ignore_ascii_case("from") && to_id.eq_ignore_ascii_case("to"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgConnectOptions;
+    use sqlx::ConnectOptions;
+    use std::str::FromStr;
+
+    // Each test gets a fresh database named after it so tests can run in
+    // parallel. They're ignored by default; run them with DATABASE_URL set and
+    // `cargo test -- --include-ignored`.
+    async fn test_database_url(name: &str) -> String {
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must point at a Postgres server");
+        let admin = PgPool::connect(&url).await.unwrap();
+        let database = format!("rhyzome_test_{}", name);
+        admin.execute(format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", database).as_str()).await.unwrap();
+        admin.execute(format!("CREATE DATABASE {}", database).as_str()).await.unwrap();
+
+        let opts = PgConnectOptions::from_str(&url).unwrap().database(&database);
+        opts.to_url_lossy().to_string()
+    }
+
+    async fn test_rhyzome(name: &str) -> Rhyzome {
+        Rhyzome::new(&test_database_url(name).await).await.unwrap()
+    }
+
+    fn sorted(mut ids: Vec<String>) -> Vec<String> {
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn recent_nodes_are_newest_first_and_truncated() {
+        let rhyzome = test_rhyzome("recent_nodes").await;
+        for (id, minutes_ago) in [("old", 30), ("newest", 0), ("middle", 10), ("older", 20)] {
+            rhyzome.set(id, id).await.unwrap();
+            sqlx::query("UPDATE nodes SET created_at = now() - make_interval(mins => $2) WHERE id = $1")
+                .bind(id)
+                .bind(minutes_ago)
+                .execute(&rhyzome.pool)
+                .await
+                .unwrap();
+        }
+
+        let recent = rhyzome.recent_nodes(3).await.unwrap();
+        let ids: Vec<&str> = recent.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["newest", "middle", "older"]);
+        assert_eq!(rhyzome.recent_nodes(usize::MAX).await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn with_retry_retries_serialization_failures() {
+        let rhyzome = test_rhyzome("with_retry").await;
+
+        let mut attempts = 0;
+        let result = rhyzome
+            .with_retry(|pool| {
+                attempts += 1;
+                let fail = attempts < 3;
+                async move {
+                    if fail {
+                        pool.execute("DO $$ BEGIN RAISE EXCEPTION 'conflict' USING ERRCODE = '40001'; END $$").await?;
+                    }
+                    Ok(attempts)
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(result, 3);
+
+        // Anything else is returned straight away
+        let mut attempts = 0;
+        let result: Result<(), Error> = rhyzome
+            .with_retry(|pool| {
+                attempts += 1;
+                async move {
+                    pool.execute("SELECT no_such_column FROM nodes").await?;
+                    Ok(())
+                }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn dedup_relations_merges_weights_into_one_row() {
+        let rhyzome = test_rhyzome("dedup_relations").await;
+        // Duplicates predate the unique index, so take it away to seed them
+        rhyzome.pool.execute("DROP INDEX relations_name_from_to").await.unwrap();
+        for (to_id, weight) in [("b", 1.0), ("b", 2.0), ("c", 1.0), ("b", 4.5)] {
+            sqlx::query("INSERT INTO relations (name, from_id, to_id, weight) VALUES ('link', 'a', $1, $2)")
+                .bind(to_id)
+                .bind(weight)
+                .execute(&rhyzome.pool)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(rhyzome.dedup_relations().await.unwrap(), 2);
+        let weights: Vec<f64> = sqlx::query_scalar("SELECT weight FROM relations WHERE to_id = 'b'")
+            .fetch_all(&rhyzome.pool)
+            .await
+            .unwrap();
+        assert_eq!(weights, [7.5]);
+        assert_eq!(sorted(rhyzome.get_related("a", "link").await.unwrap()), ["b", "c"]);
+        assert_eq!(rhyzome.dedup_relations().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn traversals_follow_the_named_relation() {
+        let rhyzome = test_rhyzome("traversals").await;
+        for (from_id, to_id) in [("a", "b"), ("a", "c"), ("b", "d")] {
+            rhyzome.relate(from_id, "follows", to_id).await.unwrap();
+        }
+        rhyzome.relate("a", "blocks", "e").await.unwrap();
+
+        assert_eq!(rhyzome.bfs("a", "follows").await.unwrap(), ["a", "b", "c", "d"]);
+        let dfs = rhyzome.dfs("a", "follows").await.unwrap();
+        assert_eq!(dfs[0], "a");
+        assert_eq!(sorted(dfs), ["a", "b", "c", "d"]);
+        assert_eq!(rhyzome.bfs("a", "related").await.unwrap(), ["a"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn relate_many_inserts_every_edge() {
+        let rhyzome = test_rhyzome("relate_many").await;
+
+        // More edges than fit in one statement's bind parameters
+        let edges: Vec<(String, String, String)> =
+            (0..25_000).map(|i| ("hub".to_string(), "link".to_string(), format!("n{}", i))).collect();
+        assert_eq!(rhyzome.relate_many(&edges[..5000]).await.unwrap(), 5000);
+        assert_eq!(rhyzome.relate_many(&edges).await.unwrap(), 20_000);
+
+        let count: i64 = sqlx::query_scalar("SELECT count(*) FROM relations").fetch_one(&rhyzome.pool).await.unwrap();
+        assert_eq!(count, 25_000);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn set_json_round_trips_and_reports_encode_errors() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Author {
+            name: String,
+            tags: Vec<String>,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Post {
+            title: String,
+            author: Author,
+            score: Option<f64>,
+        }
+
+        let rhyzome = test_rhyzome("set_json").await;
+        let post = Post {
+            title: "Hello".to_string(),
+            author: Author { name: "ada".to_string(), tags: vec!["x".to_string(), "y".to_string()] },
+            score: None,
+        };
+
+        assert_eq!(rhyzome.set_json("n", &post).await.unwrap(), SetOutcome::Insert);
+        assert_eq!(rhyzome.get_json::<Post>("n").await.unwrap(), Some(post));
+        assert!(matches!(rhyzome.get_json::<String>("n").await, Err(Error::Decode(_))));
+        assert_eq!(rhyzome.get_json::<Post>("missing").await.unwrap(), None);
+
+        // JSON object keys must be strings
+        let unencodable: std::collections::HashMap<(i32, i32), i32> = [((1, 2), 3)].into();
+        assert!(matches!(rhyzome.set_json("bad", &unencodable).await, Err(Error::Encode(_))));
+        assert_eq!(rhyzome.get("bad").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn set_reports_insert_then_update() {
+        let rhyzome = test_rhyzome("set_outcome").await;
+
+        assert_eq!(rhyzome.set("a", "1").await.unwrap(), SetOutcome::Insert);
+        assert_eq!(rhyzome.set("a", "2").await.unwrap(), SetOutcome::Update);
+        assert_eq!(rhyzome.get("a").await.unwrap().as_deref(), Some("2"));
+
+        rhyzome.delete("a").await.unwrap();
+        assert_eq!(rhyzome.set("a", "3").await.unwrap(), SetOutcome::Insert);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn relate_returns_one_id_per_edge() {
+        let rhyzome = test_rhyzome("relate_twice").await;
+
+        let id = rhyzome.relate("a", "link", "b").await.unwrap();
+        assert_eq!(rhyzome.relate("a", "link", "b").await.unwrap(), id);
+        assert_ne!(rhyzome.relate("a", "link", "c").await.unwrap(), id);
+        assert!(!rhyzome.relate_idempotent("a", "link", "b").await.unwrap());
+        assert!(rhyzome.relate_idempotent("a", "cite", "b").await.unwrap());
+
+        assert_eq!(sorted(rhyzome.get_related("a", "link").await.unwrap()), ["b", "c"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn small_pools_still_serve_concurrent_queries() {
+        let url = test_database_url("small_pool").await;
+        let rhyzome = Rhyzome::with_options(&url, PgPoolOptions::new().max_connections(2)).await.unwrap();
+        let rhyzome = std::sync::Arc::new(rhyzome);
+
+        let queries: Vec<_> = (0..8)
+            .map(|_| {
+                let rhyzome = rhyzome.clone();
+                tokio::spawn(async move { rhyzome.query("SELECT 'ok' FROM pg_sleep(0.05)").await })
+            })
+            .collect();
+        for query in queries {
+            assert_eq!(query.await.unwrap().unwrap(), ["ok"]);
+        }
+        assert!(rhyzome.pool.size() <= 2);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn get_related_checked_tells_missing_nodes_from_empty_ones() {
+        let rhyzome = test_rhyzome("get_related_checked").await;
+        rhyzome.set("a", "").await.unwrap();
+        rhyzome.set("lonely", "").await.unwrap();
+        rhyzome.relate("a", "link", "b").await.unwrap();
+        rhyzome.relate("a", "link", "c").await.unwrap();
+
+        assert_eq!(rhyzome.get_related_checked("missing", "link").await.unwrap(), None);
+        assert_eq!(rhyzome.get_related_checked("lonely", "link").await.unwrap(), Some(vec![]));
+        let related = rhyzome.get_related_checked("a", "link").await.unwrap().unwrap();
+        assert_eq!(sorted(related), ["b", "c"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn import_edges_csv_creates_every_row_after_the_header() {
+        let rhyzome = test_rhyzome("import_edges_csv").await;
+
+        let csv = "from_id,to_id\na,b  \na,c\n\nb,c\n";
+        assert_eq!(rhyzome.import_edges_csv(csv.as_bytes(), "link").await.unwrap(), 3);
+        assert_eq!(sorted(rhyzome.get_related("a", "link").await.unwrap()), ["b", "c"]);
+        assert_eq!(rhyzome.get_related("b", "link").await.unwrap(), ["c"]);
+
+        // Without a header the first row is an edge like any other
+        assert_eq!(rhyzome.import_edges_csv("x,y\n".as_bytes(), "link").await.unwrap(), 1);
+        assert_eq!(rhyzome.get_related("x", "link").await.unwrap(), ["y"]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn import_edges_csv_rejects_malformed_rows() {
+        let rhyzome = test_rhyzome("import_edges_csv_malformed").await;
+
+        let err = rhyzome.import_edges_csv("a,b\nc\n".as_bytes(), "link").await.unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(ref message) if message.contains("line 2")));
+        assert!(rhyzome.get_related("a", "link").await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_edge_row_takes_two_non_empty_fields() {
+        assert_eq!(parse_edge_row("a, b"), Some(("a", "b")));
+        assert_eq!(parse_edge_row("a,"), None);
+        assert_eq!(parse_edge_row("a,b,c"), None);
+    }
+
+    #[test]
+    fn edge_headers_name_both_columns() {
+        assert!(is_edge_header("from_id", "to_id"));
+        assert!(is_edge_header("FROM", "To"));
+        assert!(!is_edge_header("from_id", "b"));
+    }
+}